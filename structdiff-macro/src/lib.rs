@@ -1,5 +1,4 @@
 use heck::CamelCase;
-use log::{debug, error, warn};
 use proc_macro2::TokenStream;
 use quote::quote;
 use syn::DeriveInput;
@@ -7,76 +6,513 @@ use syn::DeriveInput;
 use syn::punctuated::Punctuated;
 
 fn gen_changeset_ident(ty: &syn::Ident) -> syn::Ident {
-    let v = format!("{}_Changeset", ty.to_string()).to_camel_case();
+    let v = format!("{}_Changeset", ty).to_camel_case();
+    syn::Ident::new(&v, proc_macro2::Span::call_site())
+}
+
+fn gen_changeset_in_ident(ty: &syn::Ident) -> syn::Ident {
+    let v = format!("{}_Changeset_In", ty).to_camel_case();
     syn::Ident::new(&v, proc_macro2::Span::call_site())
 }
 
 fn gen_changeset_path(ty: &syn::Path) -> syn::Path {
     let mut path = ty.to_owned();
-    let mut last_segment = path.segments.last_mut().unwrap();
+    let last_segment = path.segments.last_mut().unwrap();
 
     let s = last_segment.ident.to_string();
-    let a = last_segment.arguments.clone();
+
+    // Changesets of hashed collections do not depend on the hasher, so its
+    // parameter is dropped.
+    let len = match s.as_str() {
+        "HashMap" => Some(2),
+        "HashSet" => Some(1),
+        _ => None,
+    };
+    if let (Some(len), syn::PathArguments::AngleBracketed(args)) =
+        (len, &mut last_segment.arguments)
+    {
+        args.args = args.args.iter().take(len).cloned().collect();
+    }
 
     let v = format!("{}_Changeset", &s).to_camel_case();
     last_segment.ident = syn::Ident::new(&v, proc_macro2::Span::call_site());
     path
 }
 
-fn gen_changes(field: &syn::Field) -> TokenStream {
+fn gen_changes(field: &syn::Field, opts: &FieldOpts) -> TokenStream {
     let field_name = &field.ident;
 
+    if opts.cached_hash {
+        return quote! {
+            changes.#field_name = if structdiff::CachedHash::cached_hash(&self.#field_name)
+                == structdiff::CachedHash::cached_hash(&other.#field_name)
+            {
+                structdiff::Field::None
+            } else {
+                self.#field_name.changeset(&other.#field_name)
+            };
+        };
+    }
+
     quote! {
         changes.#field_name = self.#field_name.changeset(&other.#field_name);
     }
 }
 
-fn gen_applies(field: &syn::Field) -> TokenStream {
+fn gen_applies(field: &syn::Field, field_opts: &FieldOpts, opts: &ContainerOpts) -> TokenStream {
+    let field_name = &field.ident;
+
+    gen_traced_apply(
+        field,
+        field_opts,
+        opts,
+        quote! { self.#field_name.apply(&mut target.#field_name) },
+    )
+}
+
+fn gen_apply_refs(field: &syn::Field, field_opts: &FieldOpts, opts: &ContainerOpts) -> TokenStream {
     let field_name = &field.ident;
 
+    gen_traced_apply(
+        field,
+        field_opts,
+        opts,
+        quote! { structdiff::ApplyRef::apply_ref(&self.#field_name, &mut target.#field_name) },
+    )
+}
+
+fn gen_traced_apply(
+    field: &syn::Field,
+    field_opts: &FieldOpts,
+    opts: &ContainerOpts,
+    apply: TokenStream,
+) -> TokenStream {
+    let field_name = &field.ident;
+    let name = field_name.as_ref().unwrap().to_string();
+
+    let target = match &opts.trace_target {
+        Some(target) => quote! { #target },
+        None => quote! { module_path!() },
+    };
+
+    let level = match &opts.trace_level {
+        Some(level) => quote! { structdiff::trace::tracing::Level::#level },
+        None => quote! { structdiff::trace::tracing::Level::TRACE },
+    };
+
+    let sensitive = if field_opts.sensitive {
+        quote! { sensitive }
+    } else {
+        quote! {}
+    };
+
     quote! {
-        self.#field_name.apply(&mut target.#field_name);
+        structdiff::__trace_field!(
+            #sensitive #target,
+            #level,
+            #name,
+            self.#field_name,
+            target.#field_name,
+            #apply
+        );
     }
 }
 
-fn gen_impl_diff(ty: &syn::Ident, fields: &Punctuated<syn::Field, syn::Token![,]>) -> TokenStream {
-    let change_items = fields.iter().map(gen_changes);
-    let changeset_ident = gen_changeset_ident(&ty);
+fn gen_impl_diff(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+) -> Result<TokenStream, syn::Error> {
+    let opts = fields
+        .iter()
+        .map(|field| FieldOpts::from_attrs(&field.attrs))
+        .collect::<Result<Vec<_>, _>>()?;
+    let change_items = fields
+        .iter()
+        .zip(&opts)
+        .map(|(field, opts)| gen_changes(field, opts));
+    let changeset_ident = gen_changeset_ident(ty);
+
+    // Comparing the whole value up front would defeat the point of cached
+    // hashes, so those types instead check whether any field changed.
+    let (pre_check, post_check) = if opts.iter().any(|opts| opts.cached_hash) {
+        let field_names = fields.iter().map(|field| &field.ident);
+        let post_check = quote! {
+            if #(changes.#field_names.is_none())&&* {
+                return structdiff::Field::None
+            }
+        };
+        (quote! {}, post_check)
+    } else {
+        let pre_check = quote! {
+            if self == other {
+                return structdiff::Field::None
+            }
+        };
+        (pre_check, quote! {})
+    };
+
+    let field_types = fields.iter().map(|field| &field.ty);
+    let sensitive = opts.iter().any(|opts| opts.sensitive);
 
-    quote! {
+    Ok(quote! {
         impl structdiff::Diff for #ty {
             type Changeset = #changeset_ident;
             type Action = ();
 
+            const SENSITIVE: bool = #sensitive #(|| <#field_types as structdiff::Diff>::SENSITIVE)*;
+
             fn changeset(&self, other: &Self) -> structdiff::Field<Self, Self::Changeset, Self::Action>
             where
                 Self: Sized
             {
-                if self == other {
-                    return structdiff::Field::None
-                }
+                #pre_check
 
                 let mut changes = Self::Changeset::default();
 
                 #(#change_items)*
 
+                #post_check
+
                 structdiff::Field::Changes(changes)
             }
         }
+    })
+}
+
+fn gen_impl_diff_across(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+    other: &syn::Path,
+) -> Result<TokenStream, syn::Error> {
+    let mut change_items = vec![];
+
+    for field in fields.iter() {
+        let opts = FieldOpts::from_attrs(&field.attrs)?;
+        if opts.across_skip {
+            continue;
+        }
+
+        let field_name = &field.ident;
+        let other_name = opts.across_rename.as_ref().or(field_name.as_ref());
+        let other_value = match &opts.across_with {
+            Some(with) => quote! { &#with(&other.#other_name) },
+            None => quote! { &other.#other_name },
+        };
+
+        change_items.push(quote! {
+            changes.#field_name = self.#field_name.changeset(#other_value);
+        });
     }
+
+    let field_names = fields.iter().map(|field| &field.ident);
+
+    Ok(quote! {
+        impl structdiff::DiffAcross<#other> for #ty {
+            fn changeset_across(
+                &self,
+                other: &#other,
+            ) -> structdiff::Field<Self, Self::Changeset, Self::Action> {
+                let mut changes = Self::Changeset::default();
+
+                #(#change_items)*
+
+                if #(changes.#field_names.is_none())&&* {
+                    return structdiff::Field::None
+                }
+
+                structdiff::Field::Changes(changes)
+            }
+        }
+    })
 }
 
-fn gen_impl_apply(ty: &syn::Ident, fields: &Punctuated<syn::Field, syn::Token![,]>) -> TokenStream {
-    let apply_items = fields.iter().map(gen_applies);
-    let changeset_ident = gen_changeset_ident(&ty);
+fn gen_impl_apply(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+    opts: &ContainerOpts,
+) -> Result<TokenStream, syn::Error> {
+    let field_opts = fields
+        .iter()
+        .map(|field| FieldOpts::from_attrs(&field.attrs))
+        .collect::<Result<Vec<_>, _>>()?;
+    let apply_items = fields
+        .iter()
+        .zip(&field_opts)
+        .map(|(field, field_opts)| gen_applies(field, field_opts, opts));
+    let changeset_ident = gen_changeset_ident(ty);
+
+    // Applying by reference needs every field's type to be `Clone` and its
+    // changeset to implement `ApplyRef`, so it is only derived when asked for.
+    let apply_ref_impl = if opts.apply_ref {
+        let apply_ref_items = fields
+            .iter()
+            .zip(&field_opts)
+            .map(|(field, field_opts)| gen_apply_refs(field, field_opts, opts));
+
+        quote! {
+            impl structdiff::ApplyRef<#ty> for #changeset_ident {
+                fn apply_ref(&self, target: &mut #ty) {
+                    #(#apply_ref_items)*
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
 
-    quote! {
+    let field_names = fields.iter().map(|field| &field.ident);
+
+    Ok(quote! {
         impl structdiff::Apply<#ty> for #changeset_ident {
             fn apply(self, target: &mut #ty) {
                 #(#apply_items)*
             }
+
+            fn check(&self, target: &#ty) -> Result<(), structdiff::ApplyError> {
+                #(structdiff::Apply::check(&self.#field_names, &target.#field_names)?;)*
+                Ok(())
+            }
+        }
+
+        #apply_ref_impl
+    })
+}
+
+fn gen_impl_encoded_size(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+) -> TokenStream {
+    let changeset_ident = gen_changeset_ident(ty);
+    let size_items = fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.ident;
+            quote! { structdiff::EncodedSize::encoded_size_hint(&self.#field_name) }
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        impl structdiff::EncodedSize for #ty {
+            fn encoded_size_hint(&self) -> usize {
+                0 #(+ #size_items)*
+            }
+        }
+
+        impl structdiff::EncodedSize for #changeset_ident {
+            fn encoded_size_hint(&self) -> usize {
+                0 #(+ #size_items)*
+            }
+        }
+    }
+}
+
+fn gen_impl_canonicalize(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+) -> TokenStream {
+    let changeset_ident = gen_changeset_ident(ty);
+    let items = fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.ident;
+            quote! { structdiff::Canonicalize::canonicalize(&mut self.#field_name); }
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        impl structdiff::Canonicalize for #changeset_ident {
+            fn canonicalize(&mut self) {
+                #(#items)*
+            }
+        }
+    }
+}
+
+fn gen_impl_schema(
+    ty: &syn::Ident,
+    version: &syn::LitInt,
+    migrate: Option<&syn::Path>,
+) -> TokenStream {
+    let migrate = migrate.map(|migrate| {
+        quote! {
+            fn migrate(
+                from: u32,
+                changes: &mut structdiff::Field<Self, Self::Changeset, Self::Action>,
+            ) {
+                #migrate(from, changes)
+            }
+        }
+    });
+
+    quote! {
+        impl structdiff::schema::Schema for #ty {
+            const VERSION: u32 = #version;
+
+            #migrate
+        }
+    }
+}
+
+fn gen_impl_redact(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+) -> Result<TokenStream, syn::Error> {
+    let changeset_ident = gen_changeset_ident(ty);
+    let items = fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.ident;
+            Ok(if FieldOpts::from_attrs(&field.attrs)?.sensitive {
+                quote! { self.#field_name.redact_value(); }
+            } else {
+                quote! { structdiff::Redact::redact(&mut self.#field_name); }
+            })
+        })
+        .collect::<Result<Vec<_>, syn::Error>>()?;
+
+    Ok(quote! {
+        impl structdiff::Redact for #changeset_ident {
+            fn redact(&mut self) {
+                #(#items)*
+            }
+        }
+    })
+}
+
+/// A `Debug` impl for a changeset struct that shows only whether sensitive
+/// fields changed.
+fn gen_redacted_debug(
+    changeset_ident: &syn::Ident,
+    generics: TokenStream,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+) -> Result<TokenStream, syn::Error> {
+    let name = changeset_ident.to_string();
+    let items = fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.ident;
+            let key = field_name.as_ref().unwrap().to_string();
+            Ok(if FieldOpts::from_attrs(&field.attrs)?.sensitive {
+                quote! {
+                    .field(#key, &if self.#field_name.is_none() {
+                        structdiff::Field::<(), (), ()>::None
+                    } else {
+                        structdiff::Field::<(), (), ()>::Redacted
+                    })
+                }
+            } else {
+                quote! { .field(#key, &self.#field_name) }
+            })
+        })
+        .collect::<Result<Vec<_>, syn::Error>>()?;
+
+    Ok(quote! {
+        impl #generics std::fmt::Debug for #changeset_ident #generics {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(#name)
+                    #(#items)*
+                    .finish()
+            }
+        }
+    })
+}
+
+fn gen_arena(
+    ty: &syn::Ident,
+    fields: &Punctuated<syn::Field, syn::Token![,]>,
+    opts: &ContainerOpts,
+) -> Result<TokenStream, syn::Error> {
+    for field in fields.iter() {
+        if FieldOpts::from_attrs(&field.attrs)?.cached_hash {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`#[diff(cached_hash)]` fields are not supported with `#[diff(arena)]`",
+            ));
         }
     }
+
+    let changeset_ident = gen_changeset_in_ident(ty);
+    let apply_items = fields
+        .iter()
+        .map(|field| {
+            Ok(gen_applies(
+                field,
+                &FieldOpts::from_attrs(&field.attrs)?,
+                opts,
+            ))
+        })
+        .collect::<Result<Vec<_>, syn::Error>>()?;
+
+    let mappings = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        quote! { pub #ident : structdiff::arena::FieldOf<'bump, #ty> }
+    });
+
+    // Serialized under the owned changeset's name, so that either can be
+    // deserialized as the owned one.
+    let serde = if opts.serde {
+        let name = gen_changeset_ident(ty).to_string();
+        quote! {
+            #[derive(structdiff::schema::serde::Serialize)]
+            #[serde(crate = "structdiff::schema::serde", rename = #name)]
+        }
+    } else {
+        quote! {}
+    };
+
+    let change_items = fields.iter().map(|field| {
+        let ident = &field.ident;
+        quote! {
+            changes.#ident = structdiff::arena::DiffIn::changeset_in(&self.#ident, arena, &other.#ident);
+        }
+    });
+
+    let (derive_debug, debug_impl) = if any_sensitive(fields)? {
+        let debug_impl = gen_redacted_debug(&changeset_ident, quote! { <'bump> }, fields)?;
+        (quote! {}, debug_impl)
+    } else {
+        (quote! { #[derive(Debug)] }, quote! {})
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        #[derive(Default)]
+        #derive_debug
+        #serde
+        pub struct #changeset_ident<'bump> {
+            #(#mappings),*
+        }
+
+        #debug_impl
+
+        impl<'bump> structdiff::arena::DiffIn<'bump> for #ty {
+            type ChangesetIn = #changeset_ident<'bump>;
+            type ActionIn = ();
+
+            fn changeset_in(
+                &self,
+                arena: &'bump structdiff::arena::Bump,
+                other: &'bump Self,
+            ) -> structdiff::arena::FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+                if self == other {
+                    return structdiff::arena::FieldIn::None
+                }
+
+                let mut changes = Self::ChangesetIn::default();
+
+                #(#change_items)*
+
+                structdiff::arena::FieldIn::Changes(changes)
+            }
+        }
+
+        impl<'bump> structdiff::Apply<#ty> for #changeset_ident<'bump> {
+            fn apply(self, target: &mut #ty) {
+                #(#apply_items)*
+            }
+        }
+    })
 }
 
 fn first_generic_from_type_path(ty: &syn::Type) -> Option<syn::Type> {
@@ -98,8 +534,9 @@ fn first_generic_from_type_path(ty: &syn::Type) -> Option<syn::Type> {
 fn gen_changeset_struct(
     ty: &syn::Ident,
     fields: &Punctuated<syn::Field, syn::Token![,]>,
+    opts: &ContainerOpts,
 ) -> Result<TokenStream, syn::Error> {
-    let ty_name = gen_changeset_ident(&ty);
+    let ty_name = gen_changeset_ident(ty);
 
     let mappings = fields
         .iter()
@@ -135,16 +572,249 @@ fn gen_changeset_struct(
         })
         .collect::<Result<Vec<_>, _>>()?;
 
+    let serde = if opts.serde {
+        quote! {
+            #[derive(structdiff::schema::serde::Serialize, structdiff::schema::serde::Deserialize)]
+            #[serde(crate = "structdiff::schema::serde", default)]
+        }
+    } else {
+        quote! {}
+    };
+    let ts = if opts.ts {
+        quote! {
+            #[derive(structdiff::ts::TS)]
+            #[ts(crate = "structdiff::ts::ts_rs")]
+        }
+    } else {
+        quote! {}
+    };
+
+    let (derive_debug, debug_impl) = if any_sensitive(fields)? {
+        (quote! {}, gen_redacted_debug(&ty_name, quote! {}, fields)?)
+    } else {
+        (quote! { #[derive(Debug)] }, quote! {})
+    };
+
     Ok(quote! {
         #[automatically_derived]
-        #[derive(Debug, Default)]
+        #[derive(Default)]
+        #derive_debug
+        #serde
+        #ts
         pub struct #ty_name {
             #(#mappings),*
         }
+
+        #debug_impl
     })
 }
 
+fn any_sensitive(fields: &Punctuated<syn::Field, syn::Token![,]>) -> Result<bool, syn::Error> {
+    for field in fields.iter() {
+        if FieldOpts::from_attrs(&field.attrs)?.sensitive {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Options given by `#[diff(...)]` attributes on the deriving type.
+#[derive(Default)]
+struct ContainerOpts {
+    apply_ref: bool,
+    arena: bool,
+    canonical: bool,
+    encoded_size: bool,
+    redact: bool,
+    serde: bool,
+    ts: bool,
+    version: Option<syn::LitInt>,
+    migrate: Option<syn::Path>,
+    across: Vec<syn::Path>,
+    trace_target: Option<syn::LitStr>,
+    trace_level: Option<syn::Ident>,
+}
+
+impl ContainerOpts {
+    fn from_attrs(attrs: &[syn::Attribute]) -> Result<ContainerOpts, syn::Error> {
+        let mut opts = ContainerOpts::default();
+
+        for item in diff_attr_items(attrs)? {
+            match &item {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("apply_ref") => {
+                    opts.apply_ref = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("arena") => {
+                    opts.arena = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("canonical") => {
+                    opts.canonical = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("encoded_size") => {
+                    opts.encoded_size = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("redact") => {
+                    opts.redact = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("serde") => {
+                    opts.serde = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("ts") => {
+                    opts.serde = true;
+                    opts.ts = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Int(value),
+                    ..
+                })) if path.is_ident("version") => {
+                    opts.serde = true;
+                    opts.version = Some(value.clone());
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(value),
+                    ..
+                })) if path.is_ident("migrate") => {
+                    opts.migrate = Some(value.parse()?);
+                }
+                syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("across") => {
+                    for item in list.nested.iter() {
+                        match item {
+                            syn::NestedMeta::Meta(syn::Meta::Path(path)) => {
+                                opts.across.push(path.clone())
+                            }
+                            _ => return Err(syn::Error::new_spanned(item, "Expected a type")),
+                        }
+                    }
+                }
+                syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("trace") => {
+                    opts.parse_trace(list)?;
+                }
+                _ => return Err(syn::Error::new_spanned(item, "Unknown diff attribute")),
+            }
+        }
+
+        if let (None, Some(migrate)) = (&opts.version, &opts.migrate) {
+            return Err(syn::Error::new_spanned(
+                migrate,
+                "`migrate` requires a schema `version`",
+            ));
+        }
+
+        Ok(opts)
+    }
+
+    fn parse_trace(&mut self, list: &syn::MetaList) -> Result<(), syn::Error> {
+        for item in list.nested.iter() {
+            let (name, value) = match item {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(value),
+                    ..
+                })) => (path, value),
+                _ => return Err(syn::Error::new_spanned(item, "Expected `name = \"value\"`")),
+            };
+
+            if name.is_ident("target") {
+                self.trace_target = Some(value.clone());
+            } else if name.is_ident("level") {
+                let level = match value.value().to_lowercase().as_str() {
+                    "trace" => "TRACE",
+                    "debug" => "DEBUG",
+                    "info" => "INFO",
+                    "warn" => "WARN",
+                    "error" => "ERROR",
+                    _ => return Err(syn::Error::new_spanned(value, "Unknown tracing level")),
+                };
+                self.trace_level = Some(syn::Ident::new(level, value.span()));
+            } else {
+                return Err(syn::Error::new_spanned(name, "Unknown trace option"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Options given by `#[diff(...)]` attributes on a field.
+#[derive(Default)]
+struct FieldOpts {
+    cached_hash: bool,
+    sensitive: bool,
+    across_skip: bool,
+    across_rename: Option<syn::Ident>,
+    across_with: Option<syn::Path>,
+}
+
+impl FieldOpts {
+    fn from_attrs(attrs: &[syn::Attribute]) -> Result<FieldOpts, syn::Error> {
+        let mut opts = FieldOpts::default();
+
+        for item in diff_attr_items(attrs)? {
+            match &item {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("cached_hash") => {
+                    opts.cached_hash = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("sensitive") => {
+                    opts.sensitive = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident("across") => {
+                    opts.parse_across(list)?;
+                }
+                _ => return Err(syn::Error::new_spanned(item, "Unknown diff attribute")),
+            }
+        }
+
+        Ok(opts)
+    }
+
+    fn parse_across(&mut self, list: &syn::MetaList) -> Result<(), syn::Error> {
+        for item in list.nested.iter() {
+            match item {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("skip") => {
+                    self.across_skip = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(value),
+                    ..
+                })) if path.is_ident("rename") => {
+                    self.across_rename = Some(value.parse()?);
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(value),
+                    ..
+                })) if path.is_ident("with") => {
+                    self.across_with = Some(value.parse()?);
+                }
+                _ => return Err(syn::Error::new_spanned(item, "Unknown across attribute")),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The items of every `#[diff(...)]` attribute in `attrs`.
+fn diff_attr_items(attrs: &[syn::Attribute]) -> Result<Vec<syn::NestedMeta>, syn::Error> {
+    let mut items = vec![];
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("diff")) {
+        match attr.parse_meta()? {
+            syn::Meta::List(list) => items.extend(list.nested),
+            meta => return Err(syn::Error::new_spanned(meta, "Expected #[diff(...)]")),
+        }
+    }
+
+    Ok(items)
+}
+
 pub fn derive(input: DeriveInput) -> Result<TokenStream, syn::Error> {
+    let opts = ContainerOpts::from_attrs(&input.attrs)?;
+
     let struct_ = match &input.data {
         syn::Data::Struct(v) => v,
         syn::Data::Enum(_) => {
@@ -171,9 +841,38 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream, syn::Error> {
         }
     };
 
-    let diff_impl = gen_impl_diff(&input.ident, &fields);
-    let apply_impl = gen_impl_apply(&input.ident, &fields);
-    let changeset_struct = gen_changeset_struct(&input.ident, &fields)?;
+    let diff_impl = gen_impl_diff(&input.ident, fields)?;
+    let apply_impl = gen_impl_apply(&input.ident, fields, &opts)?;
+    let encoded_size_impl = if opts.encoded_size {
+        gen_impl_encoded_size(&input.ident, fields)
+    } else {
+        quote! {}
+    };
+    let changeset_struct = gen_changeset_struct(&input.ident, fields, &opts)?;
+    let canonicalize_impl = if opts.canonical {
+        gen_impl_canonicalize(&input.ident, fields)
+    } else {
+        quote! {}
+    };
+    let redact_impl = if opts.redact || any_sensitive(fields)? {
+        gen_impl_redact(&input.ident, fields)?
+    } else {
+        quote! {}
+    };
+    let across_impls = opts
+        .across
+        .iter()
+        .map(|other| gen_impl_diff_across(&input.ident, fields, other))
+        .collect::<Result<Vec<_>, _>>()?;
+    let schema_impl = match &opts.version {
+        Some(version) => gen_impl_schema(&input.ident, version, opts.migrate.as_ref()),
+        None => quote! {},
+    };
+    let arena = if opts.arena {
+        gen_arena(&input.ident, fields, &opts)?
+    } else {
+        quote! {}
+    };
 
     let output = quote! {
         #[automatically_derived]
@@ -182,6 +881,12 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream, syn::Error> {
         #changeset_struct
         #diff_impl
         #apply_impl
+        #encoded_size_impl
+        #canonicalize_impl
+        #redact_impl
+        #(#across_impls)*
+        #schema_impl
+        #arena
     };
 
     Ok(output)
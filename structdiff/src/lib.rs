@@ -1,5 +1,10 @@
+use smallvec::SmallVec;
 use std::fmt::Debug;
 
+// Lets derived impls in the tests below refer to this crate by name.
+#[cfg(test)]
+extern crate self as structdiff;
+
 pub trait Diff: Debug
 where
     Self: Sized,
@@ -7,11 +12,74 @@ where
     type Changeset: Debug + Apply<Self>;
     type Action: Debug + Apply<Self>;
 
+    /// Whether sequences of `Self` are compared block-wise before individual
+    /// elements are diffed. Worthwhile for plain `Copy` scalars, where slice
+    /// equality lowers to `memcmp` or a vectorised comparison.
+    const BLOCK_COMPARE: bool = false;
+
+    /// Whether `Self` holds a field marked `#[diff(sensitive)]`, directly or
+    /// in a nested type. Applying changes to such a value never logs it.
+    const SENSITIVE: bool = false;
+
     fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>;
+
+    /// The changeset that turns `Self::default()` into `self`, holding only
+    /// the parts of `self` that differ from the default.
+    ///
+    /// Applied to a value other than the default, such as when layering
+    /// configuration, the delta replaces only those parts. Collections are
+    /// merged rather than replaced: the elements of a `Vec` are appended to
+    /// the target's, and the entries of a map are inserted alongside its
+    /// existing ones.
+    fn delta_from_default(&self) -> Field<Self, Self::Changeset, Self::Action>
+    where
+        Self: Default,
+    {
+        Self::default().changeset(self)
+    }
+
+    /// Builds a value by applying `changeset` to `Self::default()`.
+    fn from_changeset(changeset: Field<Self, Self::Changeset, Self::Action>) -> Self
+    where
+        Self: Default,
+    {
+        let mut value = Self::default();
+        changeset.apply(&mut value);
+        value
+    }
+}
+
+/// Diffing against a value of another type that shares some of `Self`'s
+/// fields, such as the previous version of a struct during a migration.
+///
+/// Derive it with `#[diff(across(Other))]`. Fields are matched by name;
+/// `#[diff(across(rename = "name"))]` matches a field of `Other` with a
+/// different name, `#[diff(across(with = "path::to::fn"))]` converts the
+/// value of `Other`'s field into this field's type, and
+/// `#[diff(across(skip))]` leaves out fields `Other` does not have.
+pub trait DiffAcross<Other>: Diff {
+    /// The changeset that turns the shared fields of `self` into those of
+    /// `other`. Fields that `other` does not have are left unchanged.
+    fn changeset_across(&self, other: &Other) -> Field<Self, Self::Changeset, Self::Action>;
 }
 
 pub trait Apply<T: Sized>: Debug {
     fn apply(self, target: &mut T);
+
+    /// Checks that applying to `target` would not panic, such as by changing
+    /// an index past the end of a `Vec`. The default accepts everything, so
+    /// hand-written changesets are only checked once they implement it.
+    fn check(&self, _target: &T) -> Result<(), ApplyError> {
+        Ok(())
+    }
+
+    /// Checks a list of actions that are applied to `target` in turn.
+    fn check_all(actions: &[Self], target: &T) -> Result<(), ApplyError>
+    where
+        Self: Sized,
+    {
+        actions.iter().try_for_each(|x| x.check(target))
+    }
 }
 
 impl<T> Apply<T> for () {
@@ -19,27 +87,133 @@ impl<T> Apply<T> for () {
     fn apply(self, _target: &mut T) {}
 }
 
-#[derive(Debug)]
+/// Applies a changeset without consuming it, cloning any values it sets, so
+/// the same changeset can be pushed to many targets.
+///
+/// Derived changesets implement this with `#[diff(apply_ref)]`, which needs
+/// every field's type to be `Clone` and its changeset to implement `ApplyRef`.
+pub trait ApplyRef<T: Sized>: Apply<T> {
+    fn apply_ref(&self, target: &mut T);
+
+    fn apply_all(&self, targets: &mut [T]) {
+        targets.iter_mut().for_each(|x| self.apply_ref(x));
+    }
+}
+
+impl<T> ApplyRef<T> for () {
+    #[inline(always)]
+    fn apply_ref(&self, _target: &mut T) {}
+}
+
+/// Puts a changeset into a canonical form, so that diffing identical states
+/// produces identical changesets on every machine.
+///
+/// Changes to `HashMap` and `HashSet` are otherwise listed in the map's
+/// iteration order, which is randomised per process; canonicalizing sorts
+/// them by key. Derived changesets implement this with `#[diff(canonical)]`.
+/// Whole values carried by a changeset, such as a `Field::Set` of a map, keep
+/// their own order in memory; serialize the changeset through
+/// `schema::Canonical` to also write those maps sorted by key.
+pub trait Canonicalize {
+    fn canonicalize(&mut self);
+}
+
+impl Canonicalize for () {
+    #[inline(always)]
+    fn canonicalize(&mut self) {}
+}
+
+/// Strips sensitive values from a changeset so it can be logged or audited.
+///
+/// Fields marked `#[diff(sensitive)]` become [`Field::Redacted`], as do
+/// changes that carry a whole value of a type with a sensitive field, such as
+/// setting an `Option` of it or pushing it to a `Vec`. The `Debug` output of a
+/// changeset never shows these values. Derived changesets implement this when
+/// they have a sensitive field, or when asked to with `#[diff(redact)]` so
+/// that sensitive fields of nested types are reached.
+pub trait Redact {
+    fn redact(&mut self);
+
+    /// Whether the changeset still holds whole values of a
+    /// [sensitive](Diff::SENSITIVE) type once redacted, which can only be
+    /// removed by redacting the enclosing [`Field`] as a whole.
+    fn holds_sensitive(&self) -> bool {
+        false
+    }
+}
+
+impl Redact for () {
+    #[inline(always)]
+    fn redact(&mut self) {}
+}
+
+/// An estimate of the number of bytes a value or changeset occupies once
+/// encoded, computed without encoding it.
+///
+/// Estimates follow `bincode`'s default layout: scalars at their in-memory
+/// width, a 4-byte tag per enum variant, a 1-byte tag per `Option` and an
+/// 8-byte length prefix per string or sequence. Self-describing formats such
+/// as JSON will be larger, but scale similarly. Derived types implement this
+/// for themselves and their changeset with `#[diff(encoded_size)]`.
+pub trait EncodedSize {
+    fn encoded_size_hint(&self) -> usize;
+}
+
+/// Length prefix of an encoded string or sequence.
+const LEN_SIZE: usize = 8;
+
+/// Tag of an encoded enum variant.
+const TAG_SIZE: usize = 4;
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Field<V, K, A>
 where
     K: Apply<V>,
     A: Apply<V>,
 {
+    #[default]
     None,
     Set(V),
     Changes(K),
-    Actions(Vec<A>),
+    Actions(ActionList<A>),
+    /// A change to a sensitive value, with the value itself removed by
+    /// [`Redact::redact`]. Applying it leaves the target unchanged, and
+    /// [`Apply::check`] rejects it with [`ApplyError::Redacted`].
+    Redacted,
+}
+
+/// Storage for [`Field::Actions`]. Most changesets carry one or two actions,
+/// which are held inline rather than in a separate allocation.
+pub type ActionList<A> = SmallVec<[A; 2]>;
+
+impl<V, K, A> Field<V, K, A>
+where
+    K: Apply<V>,
+    A: Apply<V>,
+{
+    pub fn is_none(&self) -> bool {
+        matches!(self, Field::None)
+    }
+
+    /// Replaces any change with [`Field::Redacted`], keeping only the fact
+    /// that the value changed.
+    pub fn redact_value(&mut self) {
+        if !self.is_none() {
+            *self = Field::Redacted;
+        }
+    }
 }
 
 impl<V, K, A> Apply<V> for Field<V, K, A>
 where
-    V: Debug,
+    V: Diff,
     K: Debug + Apply<V>,
     A: Apply<V>,
 {
     fn apply(self, target: &mut V) {
         match self {
-            Field::None => {}
+            Field::None | Field::Redacted => {}
             Field::Set(value) => {
                 *target = value;
             }
@@ -49,11 +223,146 @@ where
             }
         }
     }
+
+    fn check(&self, target: &V) -> Result<(), ApplyError> {
+        match self {
+            Field::None | Field::Set(_) => Ok(()),
+            Field::Redacted => Err(ApplyError::Redacted),
+            Field::Changes(changeset) => changeset.check(target),
+            Field::Actions(actions) => A::check_all(actions, target),
+        }
+    }
+}
+
+impl<V, K, A> ApplyRef<V> for Field<V, K, A>
+where
+    V: Clone + Diff,
+    K: Debug + ApplyRef<V>,
+    A: ApplyRef<V>,
+{
+    fn apply_ref(&self, target: &mut V) {
+        match self {
+            Field::None | Field::Redacted => {}
+            Field::Set(value) => {
+                *target = value.clone();
+            }
+            Field::Changes(changeset) => changeset.apply_ref(target),
+            Field::Actions(actions) => {
+                actions.iter().for_each(|x| x.apply_ref(target));
+            }
+        }
+    }
+}
+
+impl<V, K, A> Canonicalize for Field<V, K, A>
+where
+    K: Canonicalize + Apply<V>,
+    A: Canonicalize + Apply<V>,
+{
+    fn canonicalize(&mut self) {
+        match self {
+            Field::None | Field::Set(_) | Field::Redacted => {}
+            Field::Changes(changeset) => changeset.canonicalize(),
+            Field::Actions(actions) => actions.iter_mut().for_each(Canonicalize::canonicalize),
+        }
+    }
+}
+
+impl<V, K, A> Redact for Field<V, K, A>
+where
+    V: Diff,
+    K: Redact + Apply<V>,
+    A: Redact + Apply<V>,
+{
+    fn redact(&mut self) {
+        let holds_sensitive = match self {
+            Field::None | Field::Redacted => false,
+            Field::Set(_) => V::SENSITIVE,
+            Field::Changes(changeset) => {
+                changeset.redact();
+                changeset.holds_sensitive()
+            }
+            Field::Actions(actions) => {
+                actions.iter_mut().for_each(Redact::redact);
+                actions.iter().any(Redact::holds_sensitive)
+            }
+        };
+
+        if holds_sensitive {
+            *self = Field::Redacted;
+        }
+    }
+}
+
+/// Stands in for a value of a sensitive type in `Debug` output.
+struct RedactedValue;
+
+impl Debug for RedactedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Redacted")
+    }
+}
+
+/// Formats `value` with `Debug`, unless it is of a
+/// [sensitive](Diff::SENSITIVE) type.
+fn redacted_if(sensitive: bool, value: &dyn Debug) -> &dyn Debug {
+    if sensitive {
+        &RedactedValue
+    } else {
+        value
+    }
+}
+
+impl<V, K, A> Debug for Field<V, K, A>
+where
+    V: Diff,
+    K: Debug + Apply<V>,
+    A: Debug + Apply<V>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::None => f.write_str("None"),
+            Field::Set(value) => f
+                .debug_tuple("Set")
+                .field(redacted_if(V::SENSITIVE, value))
+                .finish(),
+            Field::Changes(changeset) => f.debug_tuple("Changes").field(changeset).finish(),
+            Field::Actions(actions) => f.debug_tuple("Actions").field(actions).finish(),
+            Field::Redacted => f.write_str("Redacted"),
+        }
+    }
+}
+
+impl<V, K, A> EncodedSize for Field<V, K, A>
+where
+    V: EncodedSize,
+    K: EncodedSize + Apply<V>,
+    A: EncodedSize + Apply<V>,
+{
+    fn encoded_size_hint(&self) -> usize {
+        TAG_SIZE
+            + match self {
+                Field::None | Field::Redacted => 0,
+                Field::Set(value) => value.encoded_size_hint(),
+                Field::Changes(changeset) => changeset.encoded_size_hint(),
+                Field::Actions(actions) => actions.encoded_size_hint(),
+            }
+    }
+}
+
+impl<A: EncodedSize> EncodedSize for [A] {
+    fn encoded_size_hint(&self) -> usize {
+        LEN_SIZE
+            + self
+                .iter()
+                .map(EncodedSize::encoded_size_hint)
+                .sum::<usize>()
+    }
 }
 
-impl<V, K: Apply<V>, A: Apply<V>> std::default::Default for Field<V, K, A> {
-    fn default() -> Self {
-        Field::None
+impl<A: EncodedSize> EncodedSize for ActionList<A> {
+    fn encoded_size_hint(&self) -> usize {
+        self.as_slice().encoded_size_hint()
     }
 }
 
@@ -63,6 +372,8 @@ macro_rules! impl_scalar {
             type Changeset = ();
             type Action = ();
 
+            const BLOCK_COMPARE: bool = true;
+
             fn changeset(
                 &self,
                 other: &Self,
@@ -74,6 +385,31 @@ macro_rules! impl_scalar {
                 }
             }
         }
+
+        impl $crate::EncodedSize for $ty {
+            #[inline(always)]
+            fn encoded_size_hint(&self) -> usize {
+                std::mem::size_of::<$ty>()
+            }
+        }
+
+        #[cfg(feature = "bumpalo")]
+        impl<'bump> $crate::arena::DiffIn<'bump> for $ty {
+            type ChangesetIn = ();
+            type ActionIn = ();
+
+            fn changeset_in(
+                &self,
+                _arena: &'bump $crate::arena::Bump,
+                other: &'bump Self,
+            ) -> $crate::arena::FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+                if self != other {
+                    $crate::arena::FieldIn::Set(*other)
+                } else {
+                    $crate::arena::FieldIn::None
+                }
+            }
+        }
     };
 }
 
@@ -94,13 +430,40 @@ macro_rules! impl_scalar_ref {
                 }
             }
         }
+
+        #[cfg(feature = "bumpalo")]
+        impl<'bump> $crate::arena::DiffIn<'bump> for $ty {
+            type ChangesetIn = ();
+            type ActionIn = ();
+
+            fn changeset_in(
+                &self,
+                _arena: &'bump $crate::arena::Bump,
+                other: &'bump Self,
+            ) -> $crate::arena::FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+                if self != other {
+                    $crate::arena::FieldIn::Set(other.to_owned())
+                } else {
+                    $crate::arena::FieldIn::None
+                }
+            }
+        }
     };
 }
 
 use types::*;
 
 pub mod types {
-    use super::{Apply, Diff, Field};
+    use super::validate::check_element;
+    use super::{
+        redacted_if, ActionList, Apply, ApplyError, ApplyRef, Canonicalize, Diff, EncodedSize,
+        Field, Redact, LEN_SIZE, TAG_SIZE,
+    };
+    use std::collections::{HashMap, HashSet};
+    use std::fmt::Debug;
+    use std::hash::{BuildHasher, Hash};
+
+    pub use super::hashed::HashedChangeset;
 
     impl_scalar!(i8);
     pub type I8Changeset = ();
@@ -152,12 +515,74 @@ pub mod types {
     impl_scalar_ref!(String);
     pub type StringChangeset = ();
 
-    #[derive(Debug)]
+    impl EncodedSize for String {
+        fn encoded_size_hint(&self) -> usize {
+            LEN_SIZE + self.len()
+        }
+    }
+
+    impl<T: EncodedSize> EncodedSize for Vec<T> {
+        fn encoded_size_hint(&self) -> usize {
+            self.as_slice().encoded_size_hint()
+        }
+    }
+
+    impl<T: EncodedSize> EncodedSize for Option<T> {
+        fn encoded_size_hint(&self) -> usize {
+            1 + self.as_ref().map_or(0, EncodedSize::encoded_size_hint)
+        }
+    }
+
+    impl<T: EncodedSize, E: EncodedSize> EncodedSize for Result<T, E> {
+        fn encoded_size_hint(&self) -> usize {
+            TAG_SIZE
+                + match self {
+                    Ok(value) => value.encoded_size_hint(),
+                    Err(value) => value.encoded_size_hint(),
+                }
+        }
+    }
+
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "T: serde::Serialize, T::Changeset: serde::Serialize, T::Action: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, T::Changeset: serde::Deserialize<'de>, T::Action: serde::Deserialize<'de>"
+        ))
+    )]
     pub enum VecAction<T: Diff> {
         Set(usize, Field<T, <T as Diff>::Changeset, <T as Diff>::Action>),
         Push(T),
         Truncate(usize),
         Append(Vec<T>),
+        Insert(usize, T),
+        Remove(usize),
+    }
+
+    impl<T: Diff> Debug for VecAction<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            use VecAction::*;
+
+            match self {
+                Set(index, field) => f.debug_tuple("Set").field(index).field(field).finish(),
+                Push(value) => f
+                    .debug_tuple("Push")
+                    .field(redacted_if(T::SENSITIVE, value))
+                    .finish(),
+                Truncate(len) => f.debug_tuple("Truncate").field(len).finish(),
+                Append(items) => f
+                    .debug_tuple("Append")
+                    .field(redacted_if(T::SENSITIVE, items))
+                    .finish(),
+                Insert(index, value) => f
+                    .debug_tuple("Insert")
+                    .field(index)
+                    .field(redacted_if(T::SENSITIVE, value))
+                    .finish(),
+                Remove(index) => f.debug_tuple("Remove").field(index).finish(),
+            }
+        }
     }
 
     impl<T: Diff> Apply<Vec<T>> for VecAction<T> {
@@ -166,23 +591,198 @@ pub mod types {
 
             match self {
                 Set(index, field) => {
+                    #[cfg(feature = "tracing")]
+                    let _segment = crate::trace::enter(crate::trace::Segment::Index(index));
+
                     field.apply(&mut target[index]);
                 }
                 Push(value) => target.push(value),
                 Truncate(len) => target.truncate(len),
                 Append(mut items) => target.append(&mut items),
+                Insert(index, value) => target.insert(index, value),
+                Remove(index) => {
+                    target.remove(index);
+                }
+            }
+        }
+
+        fn check(&self, target: &Vec<T>) -> Result<(), ApplyError> {
+            Self::check_all(std::slice::from_ref(self), target)
+        }
+
+        fn check_all(actions: &[Self], target: &Vec<T>) -> Result<(), ApplyError> {
+            use VecAction::*;
+
+            // Each element's value as of the actions checked so far.
+            let mut slots: Vec<Option<&T>> = target.iter().map(Some).collect();
+
+            for action in actions {
+                let out_of_bounds = |index: usize, len| ApplyError::IndexOutOfBounds { index, len };
+
+                match action {
+                    Set(index, field) => {
+                        let len = slots.len();
+                        let slot = slots.get_mut(*index).ok_or(out_of_bounds(*index, len))?;
+                        check_element(field, slot)?;
+                    }
+                    Push(value) => slots.push(Some(value)),
+                    Truncate(len) => slots.truncate(*len),
+                    Append(items) => slots.extend(items.iter().map(Some)),
+                    Insert(index, value) if *index <= slots.len() => {
+                        slots.insert(*index, Some(value))
+                    }
+                    Remove(index) if *index < slots.len() => {
+                        slots.remove(*index);
+                    }
+                    Insert(index, _) | Remove(index) => {
+                        return Err(out_of_bounds(*index, slots.len()))
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    impl<T: Clone + Diff> ApplyRef<Vec<T>> for VecAction<T>
+    where
+        T::Changeset: ApplyRef<T>,
+        T::Action: ApplyRef<T>,
+    {
+        fn apply_ref(&self, target: &mut Vec<T>) {
+            use VecAction::*;
+
+            match self {
+                Set(index, field) => {
+                    #[cfg(feature = "tracing")]
+                    let _segment = crate::trace::enter(crate::trace::Segment::Index(*index));
+
+                    field.apply_ref(&mut target[*index]);
+                }
+                Push(value) => target.push(value.clone()),
+                Truncate(len) => target.truncate(*len),
+                Append(items) => target.extend_from_slice(items),
+                Insert(index, value) => target.insert(*index, value.clone()),
+                Remove(index) => {
+                    target.remove(*index);
+                }
+            }
+        }
+    }
+
+    impl<T: Diff> Canonicalize for VecAction<T>
+    where
+        T::Changeset: Canonicalize,
+        T::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            if let VecAction::Set(_, field) = self {
+                field.canonicalize();
+            }
+        }
+    }
+
+    impl<T: Diff> Redact for VecAction<T>
+    where
+        T::Changeset: Redact,
+        T::Action: Redact,
+    {
+        fn redact(&mut self) {
+            if let VecAction::Set(_, field) = self {
+                field.redact();
+            }
+        }
+
+        fn holds_sensitive(&self) -> bool {
+            use VecAction::*;
+
+            match self {
+                Push(_) | Append(_) | Insert(..) => T::SENSITIVE,
+                Set(..) | Truncate(_) | Remove(_) => false,
             }
         }
     }
 
+    impl<T> EncodedSize for VecAction<T>
+    where
+        T: Diff + EncodedSize,
+        T::Changeset: EncodedSize,
+        T::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            use VecAction::*;
+
+            TAG_SIZE
+                + match self {
+                    Set(_, field) => LEN_SIZE + field.encoded_size_hint(),
+                    Push(value) => value.encoded_size_hint(),
+                    Truncate(_) => LEN_SIZE,
+                    Append(items) => items.encoded_size_hint(),
+                    Insert(_, value) => LEN_SIZE + value.encoded_size_hint(),
+                    Remove(_) => LEN_SIZE,
+                }
+        }
+    }
+
     #[derive(Debug)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "T: serde::Serialize, T::Changeset: serde::Serialize, T::Action: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, T::Changeset: serde::Deserialize<'de>, T::Action: serde::Deserialize<'de>"
+        ))
+    )]
     pub struct VecChangeset<T: Diff>(Field<T, <T as Diff>::Changeset, <T as Diff>::Action>);
 
     impl<T: Diff> Apply<Vec<T>> for VecChangeset<T> {
-        fn apply(self, target: &mut Vec<T>) {}
+        fn apply(self, _target: &mut Vec<T>) {}
+    }
+
+    impl<T: Diff> ApplyRef<Vec<T>> for VecChangeset<T> {
+        fn apply_ref(&self, _target: &mut Vec<T>) {}
+    }
+
+    impl<T: Diff> Canonicalize for VecChangeset<T>
+    where
+        T::Changeset: Canonicalize,
+        T::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            self.0.canonicalize();
+        }
+    }
+
+    impl<T: Diff> Redact for VecChangeset<T>
+    where
+        T::Changeset: Redact,
+        T::Action: Redact,
+    {
+        fn redact(&mut self) {
+            self.0.redact();
+        }
+    }
+
+    impl<T> EncodedSize for VecChangeset<T>
+    where
+        T: Diff + EncodedSize,
+        T::Changeset: EncodedSize,
+        T::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            self.0.encoded_size_hint()
+        }
     }
 
     #[derive(Debug)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "T: serde::Serialize, T::Changeset: serde::Serialize, T::Action: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, T::Changeset: serde::Deserialize<'de>, T::Action: serde::Deserialize<'de>"
+        ))
+    )]
     pub enum OptionChangeset<T: Diff> {
         NoneChangeset(Field<(), (), ()>),
         SomeChangeset(Field<T, <T as Diff>::Changeset, <T as Diff>::Action>),
@@ -206,31 +806,112 @@ pub mod types {
                 },
             }
         }
-    }
-
-    impl<T: Diff + PartialEq + Clone> Diff for Option<T> {
-        type Changeset = OptionChangeset<T>;
-        type Action = ();
 
-        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
-        where
-            Self: Sized,
-        {
-            if self == other {
-                return Field::None;
+        fn check(&self, target: &Option<T>) -> Result<(), ApplyError> {
+            match (self, target) {
+                (OptionChangeset::NoneChangeset(_), _) => Ok(()),
+                (OptionChangeset::SomeChangeset(field), Some(value)) => field.check(value),
+                (OptionChangeset::SomeChangeset(_), None) => {
+                    Err(ApplyError::WrongVariant { expected: "Some" })
+                }
             }
+        }
+    }
 
-            let changes = match (self, other) {
-                (None, None) => OptionChangeset::NoneChangeset(Default::default()),
-                (Some(a), Some(b)) => OptionChangeset::SomeChangeset(a.changeset(&b)),
-                (_, v) => return Field::Set(v.to_owned()),
-            };
+    impl<T: Clone + Diff> ApplyRef<Option<T>> for OptionChangeset<T>
+    where
+        T::Changeset: ApplyRef<T>,
+        T::Action: ApplyRef<T>,
+    {
+        fn apply_ref(&self, target: &mut Option<T>) {
+            use OptionChangeset::*;
 
-            Field::Changes(changes)
+            match self {
+                NoneChangeset(_) => {
+                    *target = None;
+                }
+                SomeChangeset(value) => match target.as_mut() {
+                    Some(v) => value.apply_ref(v),
+                    None => unreachable!("This is a logic error."),
+                },
+            }
         }
     }
 
-    #[derive(Debug)]
+    impl<T: Diff> Canonicalize for OptionChangeset<T>
+    where
+        T::Changeset: Canonicalize,
+        T::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            if let OptionChangeset::SomeChangeset(field) = self {
+                field.canonicalize();
+            }
+        }
+    }
+
+    impl<T: Diff> Redact for OptionChangeset<T>
+    where
+        T::Changeset: Redact,
+        T::Action: Redact,
+    {
+        fn redact(&mut self) {
+            if let OptionChangeset::SomeChangeset(field) = self {
+                field.redact();
+            }
+        }
+    }
+
+    impl<T> EncodedSize for OptionChangeset<T>
+    where
+        T: Diff + EncodedSize,
+        T::Changeset: EncodedSize,
+        T::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            use OptionChangeset::*;
+
+            TAG_SIZE
+                + match self {
+                    NoneChangeset(field) => field.encoded_size_hint(),
+                    SomeChangeset(field) => field.encoded_size_hint(),
+                }
+        }
+    }
+
+    impl<T: Diff + PartialEq + Clone> Diff for Option<T> {
+        type Changeset = OptionChangeset<T>;
+        type Action = ();
+
+        const SENSITIVE: bool = T::SENSITIVE;
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
+        where
+            Self: Sized,
+        {
+            if self == other {
+                return Field::None;
+            }
+
+            let changes = match (self, other) {
+                (None, None) => OptionChangeset::NoneChangeset(Default::default()),
+                (Some(a), Some(b)) => OptionChangeset::SomeChangeset(a.changeset(b)),
+                (_, v) => return Field::Set(v.to_owned()),
+            };
+
+            Field::Changes(changes)
+        }
+    }
+
+    #[derive(Debug)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "T: serde::Serialize, T::Changeset: serde::Serialize, T::Action: serde::Serialize, E: serde::Serialize, E::Changeset: serde::Serialize, E::Action: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, T::Changeset: serde::Deserialize<'de>, T::Action: serde::Deserialize<'de>, E: serde::Deserialize<'de>, E::Changeset: serde::Deserialize<'de>, E::Action: serde::Deserialize<'de>"
+        ))
+    )]
     pub enum ResultChangeset<T: Diff, E: Diff> {
         OkChangeset(Field<T, <T as Diff>::Changeset, <T as Diff>::Action>),
         ErrChangeset(Field<E, <E as Diff>::Changeset, <E as Diff>::Action>),
@@ -251,6 +932,87 @@ pub mod types {
                 },
             }
         }
+
+        fn check(&self, target: &Result<T, E>) -> Result<(), ApplyError> {
+            use ResultChangeset::*;
+
+            match (self, target) {
+                (OkChangeset(x), Ok(inner)) => x.check(inner),
+                (ErrChangeset(x), Err(inner)) => x.check(inner),
+                (OkChangeset(_), Err(_)) => Err(ApplyError::WrongVariant { expected: "Ok" }),
+                (ErrChangeset(_), Ok(_)) => Err(ApplyError::WrongVariant { expected: "Err" }),
+            }
+        }
+    }
+
+    impl<T, E> ApplyRef<Result<T, E>> for ResultChangeset<T, E>
+    where
+        T: Clone + Diff,
+        T::Changeset: ApplyRef<T>,
+        T::Action: ApplyRef<T>,
+        E: Clone + Diff,
+        E::Changeset: ApplyRef<E>,
+        E::Action: ApplyRef<E>,
+    {
+        fn apply_ref(&self, target: &mut Result<T, E>) {
+            use ResultChangeset::*;
+
+            match (self, target.as_mut()) {
+                (OkChangeset(x), Ok(inner)) => x.apply_ref(inner),
+                (ErrChangeset(x), Err(inner)) => x.apply_ref(inner),
+                _ => unreachable!("Logic error"),
+            }
+        }
+    }
+
+    impl<T: Diff, E: Diff> Canonicalize for ResultChangeset<T, E>
+    where
+        T::Changeset: Canonicalize,
+        T::Action: Canonicalize,
+        E::Changeset: Canonicalize,
+        E::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            match self {
+                ResultChangeset::OkChangeset(field) => field.canonicalize(),
+                ResultChangeset::ErrChangeset(field) => field.canonicalize(),
+            }
+        }
+    }
+
+    impl<T: Diff, E: Diff> Redact for ResultChangeset<T, E>
+    where
+        T::Changeset: Redact,
+        T::Action: Redact,
+        E::Changeset: Redact,
+        E::Action: Redact,
+    {
+        fn redact(&mut self) {
+            match self {
+                ResultChangeset::OkChangeset(field) => field.redact(),
+                ResultChangeset::ErrChangeset(field) => field.redact(),
+            }
+        }
+    }
+
+    impl<T, E> EncodedSize for ResultChangeset<T, E>
+    where
+        T: Diff + EncodedSize,
+        T::Changeset: EncodedSize,
+        T::Action: EncodedSize,
+        E: Diff + EncodedSize,
+        E::Changeset: EncodedSize,
+        E::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            use ResultChangeset::*;
+
+            TAG_SIZE
+                + match self {
+                    OkChangeset(field) => field.encoded_size_hint(),
+                    ErrChangeset(field) => field.encoded_size_hint(),
+                }
+        }
     }
 
     impl<T, E> Diff for Result<T, E>
@@ -261,6 +1023,8 @@ pub mod types {
         type Changeset = ResultChangeset<T, E>;
         type Action = ();
 
+        const SENSITIVE: bool = T::SENSITIVE || E::SENSITIVE;
+
         fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
         where
             Self: Sized,
@@ -270,260 +1034,4271 @@ pub mod types {
             }
 
             let changes = match (self, other) {
-                (Ok(a), Ok(b)) => ResultChangeset::OkChangeset(a.changeset(&b)),
-                (Err(a), Err(b)) => ResultChangeset::ErrChangeset(a.changeset(&b)),
+                (Ok(a), Ok(b)) => ResultChangeset::OkChangeset(a.changeset(b)),
+                (Err(a), Err(b)) => ResultChangeset::ErrChangeset(a.changeset(b)),
                 (_, v) => return Field::Set(v.to_owned()),
             };
 
             Field::Changes(changes)
         }
     }
-}
 
-impl<T> Diff for Vec<T>
-where
-    T: Clone + PartialEq + Diff,
-{
-    type Changeset = VecChangeset<T>;
-    type Action = VecAction<T>;
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "K: serde::Serialize, V: serde::Serialize, V::Changeset: serde::Serialize, V::Action: serde::Serialize",
+            deserialize = "K: serde::Deserialize<'de>, V: serde::Deserialize<'de>, V::Changeset: serde::Deserialize<'de>, V::Action: serde::Deserialize<'de>"
+        ))
+    )]
+    pub enum MapAction<K, V: Diff> {
+        Insert(K, V),
+        Remove(K),
+        Set(K, Field<V, <V as Diff>::Changeset, <V as Diff>::Action>),
+    }
 
-    fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
-        if self == other {
-            return Field::None;
+    impl<K: Debug, V: Diff> Debug for MapAction<K, V> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                MapAction::Insert(key, value) => f
+                    .debug_tuple("Insert")
+                    .field(key)
+                    .field(redacted_if(V::SENSITIVE, value))
+                    .finish(),
+                MapAction::Remove(key) => f.debug_tuple("Remove").field(key).finish(),
+                MapAction::Set(key, field) => f.debug_tuple("Set").field(key).field(field).finish(),
+            }
         }
+    }
 
-        let mut changes: Vec<Self::Action> = vec![];
-
-        let min = std::cmp::min(self.len(), other.len());
-
-        for i in 0..min {
-            let changeset = self[i].changeset(&other[i]);
-            match changeset {
-                Field::None => {}
-                changeset => changes.push(VecAction::Set(i, changeset)),
+    impl<K, V: Diff> MapAction<K, V> {
+        pub fn key(&self) -> &K {
+            match self {
+                MapAction::Insert(key, _) | MapAction::Remove(key) | MapAction::Set(key, _) => key,
             }
         }
+    }
 
-        if self.len() > other.len() {
-            changes.push(VecAction::Truncate(other.len()));
-        } else if self.len() < other.len() {
-            changes.push(VecAction::Append(other[min..].to_vec()))
-        }
+    #[derive(Debug)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "MapAction<K, V>: serde::Serialize",
+            deserialize = "MapAction<K, V>: serde::Deserialize<'de>"
+        ))
+    )]
+    pub struct HashMapChangeset<K, V: Diff>(pub ActionList<MapAction<K, V>>);
 
-        Field::Actions(changes)
-    }
-}
+    impl<K, V, S> Apply<HashMap<K, V, S>> for HashMapChangeset<K, V>
+    where
+        K: Hash + Eq + Debug,
+        V: Diff,
+        S: BuildHasher,
+    {
+        fn apply(self, target: &mut HashMap<K, V, S>) {
+            for action in self.0 {
+                match action {
+                    MapAction::Insert(key, value) => {
+                        target.insert(key, value);
+                    }
+                    MapAction::Remove(key) => {
+                        target.remove(&key);
+                    }
+                    MapAction::Set(key, field) => match target.get_mut(&key) {
+                        Some(value) => field.apply(value),
+                        None => unreachable!("Logic error"),
+                    },
+                }
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        fn check(&self, target: &HashMap<K, V, S>) -> Result<(), ApplyError> {
+            // Each entry's value as of the actions checked so far.
+            let mut entries: HashMap<&K, Option<&V>> = target
+                .iter()
+                .map(|(key, value)| (key, Some(value)))
+                .collect();
 
-    type SetField<A, B> = Field<A, B, ()>;
+            for action in self.0.iter() {
+                match action {
+                    MapAction::Insert(key, value) => {
+                        entries.insert(key, Some(value));
+                    }
+                    MapAction::Remove(key) => {
+                        entries.remove(key);
+                    }
+                    MapAction::Set(key, field) => match entries.get_mut(key) {
+                        Some(slot) => check_element(field, slot)?,
+                        None => return Err(ApplyError::MissingKey(format!("{:?}", key))),
+                    },
+                }
+            }
 
-    #[derive(Debug, PartialEq, Clone)]
-    enum SomeEnum {
-        None,
-        Field1(String),
-        Field2(u32),
-        Field3(Bar),
-        Field4(u16, u16),
+            Ok(())
+        }
     }
 
-    impl std::default::Default for SomeEnum {
-        fn default() -> Self {
-            SomeEnum::None
+    impl<K, V, S> ApplyRef<HashMap<K, V, S>> for HashMapChangeset<K, V>
+    where
+        K: Clone + Hash + Eq + Debug,
+        V: Clone + Diff,
+        V::Changeset: ApplyRef<V>,
+        V::Action: ApplyRef<V>,
+        S: BuildHasher,
+    {
+        fn apply_ref(&self, target: &mut HashMap<K, V, S>) {
+            for action in self.0.iter() {
+                match action {
+                    MapAction::Insert(key, value) => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                    MapAction::Remove(key) => {
+                        target.remove(key);
+                    }
+                    MapAction::Set(key, field) => match target.get_mut(key) {
+                        Some(value) => field.apply_ref(value),
+                        None => unreachable!("Logic error"),
+                    },
+                }
+            }
         }
     }
 
-    #[derive(Debug)]
-    enum SomeEnumChangeset {
-        None(SetField<(), ()>),
-        Field1(SetField<String, StringChangeset>),
-        Field2(SetField<u32, U32Changeset>),
-        Field3(SetField<Bar, BarChangeset>),
-        Field4(SetField<u16, U16Changeset>, SetField<u16, U16Changeset>),
-    }
+    impl<K, V> Canonicalize for HashMapChangeset<K, V>
+    where
+        K: Ord,
+        V: Diff,
+        V::Changeset: Canonicalize,
+        V::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            for action in self.0.iter_mut() {
+                if let MapAction::Set(_, field) = action {
+                    field.canonicalize();
+                }
+            }
 
-    impl Apply<SomeEnum> for SomeEnumChangeset {
-        fn apply(self, target: &mut SomeEnum) {
-            use SomeEnumChangeset::*;
+            self.0.sort_by(|a, b| a.key().cmp(b.key()));
+        }
+    }
 
-            match self {
-                None(_) => {
-                    *target = SomeEnum::None;
+    impl<K, V> Redact for HashMapChangeset<K, V>
+    where
+        V: Diff,
+        V::Changeset: Redact,
+        V::Action: Redact,
+    {
+        fn redact(&mut self) {
+            for action in self.0.iter_mut() {
+                if let MapAction::Set(_, field) = action {
+                    field.redact();
                 }
-                Field1(field) => match target {
-                    SomeEnum::Field1(target) => field.apply(target),
-                    _ => unreachable!("logic error"),
-                },
-                Field2(field) => match target {
-                    SomeEnum::Field2(target) => field.apply(target),
-                    _ => unreachable!("logic error"),
-                },
-                Field3(field) => match target {
-                    SomeEnum::Field3(target) => field.apply(target),
-                    _ => unreachable!("logic error"),
-                },
-                Field4(a, b) => match target {
-                    SomeEnum::Field4(t1, t2) => {
-                        a.apply(t1);
-                        b.apply(t2);
-                    }
-                    _ => unreachable!("logic error"),
-                },
             }
         }
+
+        fn holds_sensitive(&self) -> bool {
+            V::SENSITIVE
+                && self
+                    .0
+                    .iter()
+                    .any(|action| matches!(action, MapAction::Insert(..)))
+        }
     }
 
-    #[derive(Debug, Default, PartialEq)]
-    struct Foo {
-        field_a: u32,
-        field_b: String,
-        enumer: SomeEnum,
-        bar1: Bar,
-        bar: Option<Bar>,
-        vec: Vec<String>,
+    impl<K, V> EncodedSize for HashMapChangeset<K, V>
+    where
+        K: EncodedSize,
+        V: Diff + EncodedSize,
+        V::Changeset: EncodedSize,
+        V::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            LEN_SIZE
+                + self
+                    .0
+                    .iter()
+                    .map(|action| {
+                        TAG_SIZE
+                            + action.key().encoded_size_hint()
+                            + match action {
+                                MapAction::Insert(_, value) => value.encoded_size_hint(),
+                                MapAction::Remove(_) => 0,
+                                MapAction::Set(_, field) => field.encoded_size_hint(),
+                            }
+                    })
+                    .sum::<usize>()
+        }
     }
 
-    impl Diff for SomeEnum {
-        type Changeset = SomeEnumChangeset;
+    impl<K, V, S> Diff for HashMap<K, V, S>
+    where
+        K: Clone + Hash + Eq + Debug,
+        V: Diff + PartialEq + Clone,
+        S: BuildHasher,
+    {
+        type Changeset = HashMapChangeset<K, V>;
         type Action = ();
 
-        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
-        where
-            Self: Sized,
-        {
+        const SENSITIVE: bool = V::SENSITIVE;
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
             if self == other {
                 return Field::None;
             }
 
-            use SomeEnum::*;
+            let mut actions = ActionList::new();
 
-            let changes = match (self, other) {
-                (None, None) => SomeEnumChangeset::None(().changeset(&())),
-                (Field1(a), Field1(b)) => SomeEnumChangeset::Field1(a.changeset(&b)),
-                (Field2(a), Field2(b)) => SomeEnumChangeset::Field2(a.changeset(&b)),
-                (Field3(a), Field3(b)) => SomeEnumChangeset::Field3(a.changeset(&b)),
-                (Field4(a1, a2), Field4(b1, b2)) => {
-                    SomeEnumChangeset::Field4(a1.changeset(&b1), a2.changeset(&b2))
+            for (key, value) in self.iter() {
+                match other.get(key) {
+                    Some(other) => match value.changeset(other) {
+                        Field::None => {}
+                        field => actions.push(MapAction::Set(key.clone(), field)),
+                    },
+                    None => actions.push(MapAction::Remove(key.clone())),
                 }
-                (_, v) => return Field::Set(v.to_owned()),
-            };
-
-            Field::Changes(changes)
-        }
-    }
-    impl Diff for Foo {
-        type Changeset = FooChangeset;
-        type Action = ();
-
-        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
-        where
-            Self: Sized,
-        {
-            if self == other {
-                return Field::None;
             }
 
-            let mut changes = Self::Changeset::default();
+            for (key, value) in other.iter() {
+                if !self.contains_key(key) {
+                    actions.push(MapAction::Insert(key.clone(), value.clone()));
+                }
+            }
 
-            changes.field_a = self.field_a.changeset(&other.field_a);
-            changes.field_b = self.field_b.changeset(&other.field_b);
-            changes.enumer = self.enumer.changeset(&other.enumer);
-            changes.bar = self.bar.changeset(&other.bar);
-            changes.bar1 = self.bar1.changeset(&other.bar1);
-            changes.vec = self.vec.changeset(&other.vec);
+            Field::Changes(HashMapChangeset(actions))
+        }
+    }
 
-            Field::Changes(changes)
+    impl<K: EncodedSize, V: EncodedSize, S> EncodedSize for HashMap<K, V, S> {
+        fn encoded_size_hint(&self) -> usize {
+            LEN_SIZE
+                + self
+                    .iter()
+                    .map(|(key, value)| key.encoded_size_hint() + value.encoded_size_hint())
+                    .sum::<usize>()
         }
     }
 
-    impl Diff for Bar {
-        type Changeset = BarChangeset;
-        type Action = ();
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum SetAction<T> {
+        Insert(T),
+        Remove(T),
+    }
 
-        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
-        where
-            Self: Sized,
-        {
-            if self == other {
-                return Field::None;
+    impl<T> SetAction<T> {
+        pub fn value(&self) -> &T {
+            match self {
+                SetAction::Insert(value) | SetAction::Remove(value) => value,
             }
+        }
+    }
 
-            let mut changes = Self::Changeset::default();
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct HashSetChangeset<T>(pub ActionList<SetAction<T>>);
 
-            if self.field_d != other.field_d {
-                changes.field_d = Field::Set(other.field_d.to_owned())
+    impl<T, S> Apply<HashSet<T, S>> for HashSetChangeset<T>
+    where
+        T: Hash + Eq + Debug,
+        S: BuildHasher,
+    {
+        fn apply(self, target: &mut HashSet<T, S>) {
+            for action in self.0 {
+                match action {
+                    SetAction::Insert(value) => {
+                        target.insert(value);
+                    }
+                    SetAction::Remove(value) => {
+                        target.remove(&value);
+                    }
+                }
             }
-
-            Field::Changes(changes)
         }
     }
 
-    #[derive(Debug, Clone, Default, PartialEq)]
-    struct Bar {
-        field_d: String,
-    }
+    impl<T, S> ApplyRef<HashSet<T, S>> for HashSetChangeset<T>
+    where
+        T: Clone + Hash + Eq + Debug,
+        S: BuildHasher,
+    {
+        fn apply_ref(&self, target: &mut HashSet<T, S>) {
+            for action in self.0.iter() {
+                match action {
+                    SetAction::Insert(value) => {
+                        target.insert(value.clone());
+                    }
+                    SetAction::Remove(value) => {
+                        target.remove(value);
+                    }
+                }
+            }
+        }
+    }
+
+    impl<T: Ord> Canonicalize for HashSetChangeset<T> {
+        fn canonicalize(&mut self) {
+            self.0.sort_by(|a, b| a.value().cmp(b.value()));
+        }
+    }
+
+    impl<T> Redact for HashSetChangeset<T> {
+        fn redact(&mut self) {}
+    }
+
+    impl<T: EncodedSize> EncodedSize for HashSetChangeset<T> {
+        fn encoded_size_hint(&self) -> usize {
+            LEN_SIZE
+                + self
+                    .0
+                    .iter()
+                    .map(|action| TAG_SIZE + action.value().encoded_size_hint())
+                    .sum::<usize>()
+        }
+    }
+
+    impl<T, S> Diff for HashSet<T, S>
+    where
+        T: Clone + Hash + Eq + Debug,
+        S: BuildHasher,
+    {
+        type Changeset = HashSetChangeset<T>;
+        type Action = ();
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
+            if self == other {
+                return Field::None;
+            }
+
+            let removed = self.difference(other).cloned().map(SetAction::Remove);
+            let inserted = other.difference(self).cloned().map(SetAction::Insert);
+
+            Field::Changes(HashSetChangeset(removed.chain(inserted).collect()))
+        }
+    }
+
+    impl<T: EncodedSize, S> EncodedSize for HashSet<T, S> {
+        fn encoded_size_hint(&self) -> usize {
+            LEN_SIZE
+                + self
+                    .iter()
+                    .map(EncodedSize::encoded_size_hint)
+                    .sum::<usize>()
+        }
+    }
+}
+
+impl<T> Diff for Vec<T>
+where
+    T: Clone + PartialEq + Diff,
+{
+    type Changeset = VecChangeset<T>;
+    type Action = VecAction<T>;
+
+    const SENSITIVE: bool = T::SENSITIVE;
+
+    fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
+        if self == other {
+            return Field::None;
+        }
+
+        let mut changes = ActionList::new();
+
+        let min = std::cmp::min(self.len(), other.len());
+
+        diff_slices(&self[..min], &other[..min], 0, &mut changes);
+
+        changes.extend(vec_tail_action(self, other));
+
+        Field::Actions(changes)
+    }
+}
+
+/// Number of elements compared at once for types with [`Diff::BLOCK_COMPARE`].
+const BLOCK_LEN: usize = 64;
+
+/// Pushes a `VecAction::Set` for each differing element of two equal-length
+/// slices, numbering elements from `offset`.
+fn diff_slices<T>(a: &[T], b: &[T], offset: usize, changes: &mut ActionList<VecAction<T>>)
+where
+    T: PartialEq + Diff,
+{
+    for_each_candidate(a, b, offset, |i, a, b| match a.changeset(b) {
+        Field::None => {}
+        changeset => changes.push(VecAction::Set(i, changeset)),
+    });
+}
+
+/// Calls `f` with the index and elements of each position at which two
+/// equal-length slices may differ, skipping equal blocks for types with
+/// [`Diff::BLOCK_COMPARE`].
+fn for_each_candidate<'a, 'b, T, F>(a: &'a [T], b: &'b [T], offset: usize, mut f: F)
+where
+    T: PartialEq + Diff,
+    F: FnMut(usize, &'a T, &'b T),
+{
+    if !T::BLOCK_COMPARE {
+        for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+            f(offset + i, a, b);
+        }
+        return;
+    }
+
+    let blocks = a.chunks(BLOCK_LEN).zip(b.chunks(BLOCK_LEN));
+
+    for (n, (a, b)) in blocks.enumerate() {
+        if a != b {
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                f(offset + n * BLOCK_LEN + i, a, b);
+            }
+        }
+    }
+}
+
+/// The action that reconciles the lengths of `a` and `b` once their common
+/// prefix has been diffed, if they differ in length.
+fn vec_tail_action<T: Clone + Diff>(a: &[T], b: &[T]) -> Option<VecAction<T>> {
+    if a.len() > b.len() {
+        Some(VecAction::Truncate(b.len()))
+    } else if a.len() < b.len() {
+        Some(VecAction::Append(b[a.len()..].to_vec()))
+    } else {
+        None
+    }
+}
+
+pub use hashed::{CachedHash, Hashed};
+
+/// Content hashes that let unchanged subtrees be skipped without comparing
+/// them.
+///
+/// Derived types consult a field's [`CachedHash`] before diffing it when the
+/// field is marked `#[diff(cached_hash)]`, and skip both the comparison and
+/// the recursion when the hashes match.
+pub mod hashed {
+    use super::{Apply, ApplyError, ApplyRef, Canonicalize, Diff, EncodedSize, Field, Redact};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::ops::Deref;
+
+    pub trait CachedHash {
+        /// A hash of the value's content, maintained alongside the value so
+        /// that it is not recomputed on every comparison.
+        fn cached_hash(&self) -> u64;
+    }
+
+    /// A value paired with a hash of its content.
+    ///
+    /// Two values are equal when their hashes are, without comparing the
+    /// values themselves, so that neither comparing nor diffing walks an
+    /// unchanged subtree. Values whose hashes collide therefore compare equal
+    /// and are not diffed; use a hash wide enough to make that unlikely.
+    ///
+    /// Hashes from [`Hashed::new`] use the standard library's
+    /// `DefaultHasher`, which is only stable within a single Rust release;
+    /// use [`Hashed::with_hash`] for hashes that are persisted or sent to
+    /// other machines.
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Hashed<T> {
+        value: T,
+        #[cfg_attr(feature = "serde", serde(with = "hash_repr"))]
+        hash: u64,
+    }
+
+    impl<T: Hash> Hashed<T> {
+        pub fn new(value: T) -> Self {
+            let hash = hash_of(&value);
+            Hashed { value, hash }
+        }
+
+        /// Modifies the value in place, then rehashes it.
+        pub fn update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+            let result = f(&mut self.value);
+            self.hash = hash_of(&self.value);
+            result
+        }
+    }
+
+    impl<T> Hashed<T> {
+        /// Pairs `value` with a hash computed by the caller. The hash must
+        /// change whenever the value does.
+        pub fn with_hash(value: T, hash: u64) -> Self {
+            Hashed { value, hash }
+        }
+
+        pub fn into_inner(self) -> T {
+            self.value
+        }
+    }
+
+    /// Hashes are written as strings in human-readable formats, as readers
+    /// such as JavaScript would lose precision on a 64-bit number.
+    #[cfg(feature = "serde")]
+    mod hash_repr {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                serializer.collect_str(hash)
+            } else {
+                serializer.serialize_u64(*hash)
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+            if deserializer.is_human_readable() {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            } else {
+                u64::deserialize(deserializer)
+            }
+        }
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    impl<T> CachedHash for Hashed<T> {
+        fn cached_hash(&self) -> u64 {
+            self.hash
+        }
+    }
+
+    impl<T> Deref for Hashed<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.value
+        }
+    }
+
+    impl<T: Hash + Default> Default for Hashed<T> {
+        fn default() -> Self {
+            Hashed::new(T::default())
+        }
+    }
+
+    impl<T> PartialEq for Hashed<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.hash == other.hash
+        }
+    }
+
+    impl<T> Eq for Hashed<T> {}
+
+    impl<T> Hash for Hashed<T> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            state.write_u64(self.hash);
+        }
+    }
+
+    impl<T: EncodedSize> EncodedSize for Hashed<T> {
+        fn encoded_size_hint(&self) -> usize {
+            8 + self.value.encoded_size_hint()
+        }
+    }
+
+    #[derive(Debug)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(bound(
+            serialize = "T: serde::Serialize, T::Changeset: serde::Serialize, T::Action: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, T::Changeset: serde::Deserialize<'de>, T::Action: serde::Deserialize<'de>"
+        ))
+    )]
+    pub struct HashedChangeset<T: Diff> {
+        #[cfg_attr(feature = "serde", serde(with = "hash_repr"))]
+        pub hash: u64,
+        pub changes: Field<T, <T as Diff>::Changeset, <T as Diff>::Action>,
+    }
+
+    impl<T: Diff> Apply<Hashed<T>> for HashedChangeset<T> {
+        fn apply(self, target: &mut Hashed<T>) {
+            self.changes.apply(&mut target.value);
+            target.hash = self.hash;
+        }
+
+        fn check(&self, target: &Hashed<T>) -> Result<(), ApplyError> {
+            self.changes.check(&target.value)
+        }
+    }
+
+    impl<T: Diff> Canonicalize for HashedChangeset<T>
+    where
+        T::Changeset: Canonicalize,
+        T::Action: Canonicalize,
+    {
+        fn canonicalize(&mut self) {
+            self.changes.canonicalize();
+        }
+    }
+
+    impl<T: Diff> Redact for HashedChangeset<T>
+    where
+        T::Changeset: Redact,
+        T::Action: Redact,
+    {
+        fn redact(&mut self) {
+            self.changes.redact();
+        }
+    }
+
+    impl<T: Clone + Diff> ApplyRef<Hashed<T>> for HashedChangeset<T>
+    where
+        T::Changeset: ApplyRef<T>,
+        T::Action: ApplyRef<T>,
+    {
+        fn apply_ref(&self, target: &mut Hashed<T>) {
+            self.changes.apply_ref(&mut target.value);
+            target.hash = self.hash;
+        }
+    }
+
+    impl<T> EncodedSize for HashedChangeset<T>
+    where
+        T: Diff + EncodedSize,
+        T::Changeset: EncodedSize,
+        T::Action: EncodedSize,
+    {
+        fn encoded_size_hint(&self) -> usize {
+            8 + self.changes.encoded_size_hint()
+        }
+    }
+
+    impl<T: Diff> Diff for Hashed<T> {
+        type Changeset = HashedChangeset<T>;
+        type Action = ();
+
+        const SENSITIVE: bool = T::SENSITIVE;
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
+            if self.hash == other.hash {
+                return Field::None;
+            }
+
+            Field::Changes(HashedChangeset {
+                hash: other.hash,
+                changes: self.value.changeset(&other.value),
+            })
+        }
+    }
+}
+
+pub use myers::MinimalDiff;
+
+/// Minimal edit scripts for sequences, using Myers' algorithm.
+///
+/// Finding the shortest edit script costs O((N + M) * D) time for an edit
+/// distance of D, which is quadratic for inputs that share little. The
+/// search is abandoned once D exceeds a caller-provided cost, falling back to
+/// the linear diff by index of [`Diff::changeset`].
+pub mod myers {
+    use super::{ActionList, Diff, Field, VecAction};
+
+    /// A cost cutoff suitable for interactive use.
+    pub const DEFAULT_MAX_COST: usize = 1024;
+
+    pub trait MinimalDiff: Diff {
+        /// A changeset expressed as insertions, removals and in-place
+        /// changes, or [`Diff::changeset`] if more than `max_cost` elements
+        /// would need to be inserted or removed.
+        fn minimal_changeset(
+            &self,
+            other: &Self,
+            max_cost: usize,
+        ) -> Field<Self, Self::Changeset, Self::Action>;
+    }
+
+    impl<T> MinimalDiff for Vec<T>
+    where
+        T: Clone + PartialEq + Diff,
+    {
+        fn minimal_changeset(
+            &self,
+            other: &Self,
+            max_cost: usize,
+        ) -> Field<Self, Self::Changeset, Self::Action> {
+            if self == other {
+                return Field::None;
+            }
+
+            match shortest_edit(self, other, max_cost) {
+                Some(edits) => Field::Actions(to_actions(self, other, edits)),
+                None => self.changeset(other),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Edit {
+        Keep,
+        Delete(usize),
+        Insert(usize),
+    }
+
+    /// The shortest edit script turning `a` into `b`, or `None` if it is
+    /// longer than `max_cost`.
+    fn shortest_edit<T: PartialEq>(a: &[T], b: &[T], max_cost: usize) -> Option<Vec<Edit>> {
+        let (n, m) = (a.len() as isize, b.len() as isize);
+        let limit = std::cmp::min(a.len() + b.len(), max_cost) as isize;
+
+        // `v[offset + k]` is the furthest `x` reached on diagonal `k = x - y`.
+        // The window of `v` around the diagonals reachable in each round is
+        // kept in `trace` for backtracking.
+        let offset = limit + 1;
+        let mut v = vec![0isize; 2 * offset as usize + 1];
+        let mut trace = vec![];
+
+        for d in 0..=limit {
+            trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+            for k in (-d..=d).step_by(2) {
+                let i = (offset + k) as usize;
+                let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                    v[i + 1]
+                } else {
+                    v[i - 1] + 1
+                };
+                let mut y = x - k;
+
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+
+                v[i] = x;
+
+                if x >= n && y >= m {
+                    return Some(backtrack(&trace, n, m));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+        let mut edits = vec![];
+        let (mut x, mut y) = (n, m);
+
+        for (d, v) in trace.iter().enumerate().rev() {
+            let d = d as isize;
+            // Index into the window saved for round `d`.
+            let at = |k: isize| v[(k + d + 1) as usize];
+
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = at(prev_k);
+            let prev_y = prev_x - prev_k;
+
+            while x > prev_x && y > prev_y {
+                edits.push(Edit::Keep);
+                x -= 1;
+                y -= 1;
+            }
+
+            if d > 0 {
+                if x == prev_x {
+                    edits.push(Edit::Insert(prev_y as usize));
+                } else {
+                    edits.push(Edit::Delete(prev_x as usize));
+                }
+            }
+
+            x = prev_x;
+            y = prev_y;
+        }
+
+        edits.reverse();
+        edits
+    }
+
+    /// Converts an edit script into actions applied in order, pairing each
+    /// adjacent deletion and insertion into a single in-place change.
+    fn to_actions<T>(a: &[T], b: &[T], edits: Vec<Edit>) -> ActionList<VecAction<T>>
+    where
+        T: Clone + Diff,
+    {
+        let mut actions = ActionList::new();
+        let mut edits = edits.into_iter().peekable();
+        let mut index = 0;
+
+        while let Some(edit) = edits.next() {
+            match (edit, edits.peek()) {
+                (Edit::Keep, _) => {}
+                (Edit::Delete(i), Some(&Edit::Insert(j)))
+                | (Edit::Insert(j), Some(&Edit::Delete(i))) => {
+                    edits.next();
+
+                    match a[i].changeset(&b[j]) {
+                        Field::None => {}
+                        changeset => actions.push(VecAction::Set(index, changeset)),
+                    }
+                }
+                (Edit::Delete(_), _) => {
+                    actions.push(VecAction::Remove(index));
+                    continue;
+                }
+                (Edit::Insert(j), _) => actions.push(VecAction::Insert(index, b[j].clone())),
+            }
+
+            index += 1;
+        }
+
+        actions
+    }
+}
+
+pub use stream::{diff_streaming, DiffStream};
+
+/// Diffing of sequences too large to hold in memory.
+pub mod stream {
+    use super::{Diff, Field, VecAction};
+    use std::iter::Fuse;
+
+    /// An iterator over the actions that turn the sequence `a` into `b`,
+    /// consuming both inputs one element at a time.
+    ///
+    /// Differing elements yield `VecAction::Set`, and the end of the shorter
+    /// input yields either a `VecAction::Push` for each remaining element of
+    /// `b` or a single `VecAction::Truncate`. Applying the actions in order
+    /// to a `Vec` equal to `a` makes it equal to `b`.
+    pub struct DiffStream<A: Iterator, B: Iterator> {
+        a: Fuse<A>,
+        b: Fuse<B>,
+        index: usize,
+        done: bool,
+    }
+
+    impl<T, A, B> DiffStream<A, B>
+    where
+        T: Diff,
+        A: Iterator<Item = T>,
+        B: Iterator<Item = T>,
+    {
+        pub fn new<IA, IB>(a: IA, b: IB) -> Self
+        where
+            IA: IntoIterator<IntoIter = A>,
+            IB: IntoIterator<IntoIter = B>,
+        {
+            DiffStream {
+                a: a.into_iter().fuse(),
+                b: b.into_iter().fuse(),
+                index: 0,
+                done: false,
+            }
+        }
+    }
+
+    impl<T, A, B> Iterator for DiffStream<A, B>
+    where
+        T: Diff,
+        A: Iterator<Item = T>,
+        B: Iterator<Item = T>,
+    {
+        type Item = VecAction<T>;
+
+        fn next(&mut self) -> Option<VecAction<T>> {
+            if self.done {
+                return None;
+            }
+
+            loop {
+                let index = self.index;
+
+                match (self.a.next(), self.b.next()) {
+                    (Some(a), Some(b)) => {
+                        self.index += 1;
+
+                        match a.changeset(&b) {
+                            Field::None => {}
+                            changeset => return Some(VecAction::Set(index, changeset)),
+                        }
+                    }
+                    (None, Some(b)) => {
+                        self.index += 1;
+                        return Some(VecAction::Push(b));
+                    }
+                    (Some(_), None) => {
+                        self.done = true;
+                        return Some(VecAction::Truncate(index));
+                    }
+                    (None, None) => {
+                        self.done = true;
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with each action that turns the sequence `a` into `b`, as
+    /// it is discovered. See [`DiffStream`].
+    pub fn diff_streaming<T, A, B, F>(a: A, b: B, f: F)
+    where
+        T: Diff,
+        A: IntoIterator<Item = T>,
+        B: IntoIterator<Item = T>,
+        F: FnMut(VecAction<T>),
+    {
+        DiffStream::new(a, b).for_each(f);
+    }
+}
+
+pub use store::ChangesetStore;
+
+/// Type-erased storage for changesets of many different types.
+pub mod store {
+    use super::{Apply, Diff, Field};
+    use std::any::{Any, TypeId};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::hash::Hash;
+
+    type Changes<T> = Field<T, <T as Diff>::Changeset, <T as Diff>::Action>;
+
+    /// Queued changesets for values of mixed types, keyed by an id such as an
+    /// entity.
+    ///
+    /// Each id holds a separate queue per type, so the same entity can have
+    /// pending changes for several of its components. Changesets are applied
+    /// in the order they were inserted.
+    pub struct ChangesetStore<I> {
+        queues: HashMap<(I, TypeId), Vec<Box<dyn Any + Send>>>,
+    }
+
+    impl<I: Hash + Eq> ChangesetStore<I> {
+        pub fn new() -> Self {
+            ChangesetStore {
+                queues: HashMap::new(),
+            }
+        }
+
+        /// Queues `changeset` for the value of type `T` identified by `id`.
+        pub fn insert<T>(&mut self, id: I, changeset: Changes<T>)
+        where
+            T: Diff + 'static,
+            Changes<T>: Send,
+        {
+            if changeset.is_none() {
+                return;
+            }
+
+            self.queues
+                .entry((id, TypeId::of::<T>()))
+                .or_default()
+                .push(Box::new(changeset));
+        }
+
+        /// Applies and removes every changeset queued for the `T` identified
+        /// by `id`, returning whether there were any.
+        pub fn apply_to<T>(&mut self, id: I, target: &mut T) -> bool
+        where
+            T: Diff + 'static,
+            Changes<T>: Send,
+        {
+            let changesets = self.take::<T>(id);
+            let applied = !changesets.is_empty();
+
+            for changeset in changesets {
+                changeset.apply(target);
+            }
+
+            applied
+        }
+
+        /// Removes and returns every changeset queued for the `T` identified
+        /// by `id`, in insertion order.
+        pub fn take<T>(&mut self, id: I) -> Vec<Changes<T>>
+        where
+            T: Diff + 'static,
+            Changes<T>: Send,
+        {
+            self.queues
+                .remove(&(id, TypeId::of::<T>()))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|changeset| match changeset.downcast::<Changes<T>>() {
+                    Ok(changeset) => *changeset,
+                    Err(_) => unreachable!("Changesets are keyed by their type"),
+                })
+                .collect()
+        }
+
+        /// Whether any changesets are queued for the `T` identified by `id`.
+        pub fn contains<T: Diff + 'static>(&self, id: I) -> bool {
+            self.queues.contains_key(&(id, TypeId::of::<T>()))
+        }
+
+        /// The number of changesets queued across all ids and types.
+        pub fn len(&self) -> usize {
+            self.queues.values().map(Vec::len).sum()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.queues.is_empty()
+        }
+
+        pub fn clear(&mut self) {
+            self.queues.clear();
+        }
+    }
+
+    impl<I: Hash + Eq> Default for ChangesetStore<I> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<I: Hash + Eq> fmt::Debug for ChangesetStore<I> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ChangesetStore")
+                .field("len", &self.len())
+                .finish()
+        }
+    }
+}
+
+pub use validate::{ApplyError, Validate};
+
+/// Checking changesets before they are applied.
+///
+/// Changesets from untrusted peers may not fit the value they are applied
+/// to, such as by changing an index past the end of a `Vec`, which would
+/// panic on [`Apply::apply`]. [`Apply::check`] finds these without changing
+/// anything, and [`Field::apply_validated`] runs it ahead of the validator.
+pub mod validate {
+    use super::{Apply, ApplyRef, Diff, Field};
+    use std::fmt;
+
+    /// Why a changeset cannot be applied to a value.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ApplyError {
+        IndexOutOfBounds {
+            index: usize,
+            len: usize,
+        },
+        /// A change to a map entry that does not exist, with the key
+        /// formatted with `Debug`.
+        MissingKey(String),
+        /// Changes to an enum variant the target does not hold, such as
+        /// changes to a `Some` applied to `None`.
+        WrongVariant {
+            expected: &'static str,
+        },
+        /// A change inside an element that an earlier action in the same
+        /// changeset already changed in place, which cannot be checked without
+        /// applying the earlier action.
+        RepeatedChange,
+        /// A change whose value was removed by [`Redact`](crate::Redact), and
+        /// which would be skipped by `apply`.
+        Redacted,
+    }
+
+    impl fmt::Display for ApplyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ApplyError::IndexOutOfBounds { index, len } => {
+                    write!(f, "index {} is out of bounds for length {}", index, len)
+                }
+                ApplyError::MissingKey(key) => write!(f, "no entry for key {}", key),
+                ApplyError::WrongVariant { expected } => {
+                    write!(f, "changes are for `{}`, but the value is not", expected)
+                }
+                ApplyError::RepeatedChange => {
+                    write!(
+                        f,
+                        "an element is changed again after being changed in place"
+                    )
+                }
+                ApplyError::Redacted => write!(f, "the change was redacted"),
+            }
+        }
+    }
+
+    impl std::error::Error for ApplyError {}
+
+    /// Checks changes to an element of a collection, given its value as of
+    /// the actions checked so far, then records its value after them. `slot`
+    /// is `None` once the element has been changed in place.
+    pub(crate) fn check_element<'a, V, K, A>(
+        field: &'a Field<V, K, A>,
+        slot: &mut Option<&'a V>,
+    ) -> Result<(), ApplyError>
+    where
+        V: Diff,
+        K: fmt::Debug + Apply<V>,
+        A: Apply<V>,
+    {
+        match field {
+            Field::None => Ok(()),
+            Field::Redacted => Err(ApplyError::Redacted),
+            Field::Set(value) => {
+                *slot = Some(value);
+                Ok(())
+            }
+            Field::Changes(_) | Field::Actions(_) => match slot.take() {
+                Some(value) => field.check(value),
+                None => Err(ApplyError::RepeatedChange),
+            },
+        }
+    }
+
+    type Changes<T> = Field<T, <T as Diff>::Changeset, <T as Diff>::Action>;
+
+    /// A check run against a changeset before it is applied, such as a range
+    /// check on a field or an invariant across several fields.
+    ///
+    /// Implemented for closures taking the target and the changes, so a check
+    /// can be given per call or implemented once on a type and reused.
+    pub trait Validate<T: Diff> {
+        type Error;
+
+        fn validate(&self, target: &T, changes: &Changes<T>) -> Result<(), Self::Error>;
+    }
+
+    impl<T, E, F> Validate<T> for F
+    where
+        T: Diff,
+        F: Fn(&T, &Changes<T>) -> Result<(), E>,
+    {
+        type Error = E;
+
+        fn validate(&self, target: &T, changes: &Changes<T>) -> Result<(), E> {
+            self(target, changes)
+        }
+    }
+
+    impl<T: Diff> Field<T, T::Changeset, T::Action> {
+        /// Applies the changes to `target` if they [fit](Apply::check) and
+        /// `validator` accepts them. On error, `target` is left untouched.
+        pub fn apply_validated<V>(self, target: &mut T, validator: &V) -> Result<(), V::Error>
+        where
+            V: Validate<T>,
+            V::Error: From<ApplyError>,
+        {
+            self.check(target)?;
+            validator.validate(target, &self)?;
+            self.apply(target);
+            Ok(())
+        }
+
+        /// The value `target` would have after applying the changes, for
+        /// validators that check the result as a whole.
+        pub fn preview(&self, target: &T) -> Result<T, ApplyError>
+        where
+            T: Clone,
+            T::Changeset: ApplyRef<T>,
+            T::Action: ApplyRef<T>,
+        {
+            self.check(target)?;
+            let mut value = target.clone();
+            self.apply_ref(&mut value);
+            Ok(value)
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+pub use par::{ParApply, ParDiff};
+
+/// Parallel diffing of large collections, backed by `rayon`.
+///
+/// This is a separate trait rather than a change to [`Diff`] itself so that
+/// enabling the feature does not add `Send`/`Sync` requirements to every
+/// element type.
+#[cfg(feature = "rayon")]
+pub mod par {
+    use super::types::{HashMapChangeset, MapAction};
+    use super::{diff_slices, vec_tail_action, ActionList, ApplyRef, Diff, Field, BLOCK_LEN};
+    use rayon::prelude::*;
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::hash::{BuildHasher, Hash};
+
+    /// Collections with fewer common elements than this are diffed on the
+    /// current thread, as splitting the work would cost more than it saves.
+    pub const PAR_THRESHOLD: usize = 4096;
+
+    pub trait ParDiff: Diff {
+        /// Equivalent to [`Diff::changeset`], with element or entry changesets
+        /// computed on the rayon thread pool. Actions are emitted in the same
+        /// order as the serial implementation.
+        fn par_changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>;
+    }
+
+    impl<T> ParDiff for Vec<T>
+    where
+        T: Clone + PartialEq + Diff + Send + Sync,
+        T::Changeset: Send,
+        T::Action: Send,
+    {
+        fn par_changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
+            let min = std::cmp::min(self.len(), other.len());
+
+            if min < PAR_THRESHOLD {
+                return self.changeset(other);
+            }
+
+            let changes: Vec<Self::Action> = self[..min]
+                .par_chunks(BLOCK_LEN)
+                .zip(other[..min].par_chunks(BLOCK_LEN))
+                .enumerate()
+                .flat_map_iter(|(n, (a, b))| {
+                    let mut changes = ActionList::new();
+                    diff_slices(a, b, n * BLOCK_LEN, &mut changes);
+                    changes
+                })
+                .collect();
+
+            let mut changes = ActionList::from_vec(changes);
+            changes.extend(vec_tail_action(self, other));
+
+            if changes.is_empty() {
+                Field::None
+            } else {
+                Field::Actions(changes)
+            }
+        }
+    }
+
+    impl<K, V, S> ParDiff for HashMap<K, V, S>
+    where
+        K: Clone + Hash + Eq + Debug + Send + Sync,
+        V: Diff + PartialEq + Clone + Send + Sync,
+        V::Changeset: Send,
+        V::Action: Send,
+        S: BuildHasher + Sync,
+    {
+        fn par_changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action> {
+            if std::cmp::max(self.len(), other.len()) < PAR_THRESHOLD {
+                return self.changeset(other);
+            }
+
+            if self == other {
+                return Field::None;
+            }
+
+            let changed: Vec<MapAction<K, V>> = self
+                .par_iter()
+                .filter_map(|(key, value)| match other.get(key) {
+                    Some(other) => match value.changeset(other) {
+                        Field::None => None,
+                        field => Some(MapAction::Set(key.clone(), field)),
+                    },
+                    None => Some(MapAction::Remove(key.clone())),
+                })
+                .collect();
+
+            let inserted: Vec<MapAction<K, V>> = other
+                .par_iter()
+                .filter(|(key, _)| !self.contains_key(*key))
+                .map(|(key, value)| MapAction::Insert(key.clone(), value.clone()))
+                .collect();
+
+            Field::Changes(HashMapChangeset(
+                changed.into_iter().chain(inserted).collect(),
+            ))
+        }
+    }
+
+    pub trait ParApply<T: Sized>: ApplyRef<T> + Sync {
+        /// Equivalent to [`ApplyRef::apply_all`], with targets updated on the
+        /// rayon thread pool.
+        fn par_apply_all(&self, targets: &mut [T]);
+    }
+
+    impl<T, A> ParApply<T> for A
+    where
+        T: Send,
+        A: ApplyRef<T> + Sync,
+    {
+        fn par_apply_all(&self, targets: &mut [T]) {
+            targets.par_iter_mut().for_each(|x| self.apply_ref(x));
+        }
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+pub use arena::DiffIn;
+
+/// Changesets allocated from a caller-provided [`Bump`] arena.
+///
+/// [`DiffIn::changeset_in`] mirrors [`Diff::changeset`], but every action list
+/// is allocated from the arena and appended tails are borrowed from `other`
+/// rather than copied, so a diff of `Copy` data makes no individual heap
+/// allocations and is freed with the arena's next `reset`. Values held by
+/// `FieldIn::Set` are cloned as usual, so types that own heap data (such as
+/// `String`) still allocate for those values.
+///
+/// With the `serde` feature, arena changesets serialize exactly as their
+/// owned counterparts do, so a changeset can be sent without copying the
+/// tails it borrows and deserialized on the other end as a [`Field`].
+///
+/// Derived types opt in with `#[diff(arena)]`.
+#[cfg(feature = "bumpalo")]
+pub mod arena {
+    use super::{for_each_candidate, redacted_if, Apply, Diff};
+    use bumpalo::collections::Vec as BumpVec;
+    use std::borrow::Cow;
+    use std::fmt::Debug;
+
+    pub use bumpalo::{self, Bump};
+
+    /// Changesets borrow from both the arena and `other`, so `'bump` is
+    /// bounded by whichever of the two is dropped first.
+    pub trait DiffIn<'bump>: Diff + 'bump {
+        type ChangesetIn: Debug + Apply<Self> + 'bump;
+        type ActionIn: Debug + Apply<Self> + 'bump;
+
+        fn changeset_in(
+            &self,
+            arena: &'bump Bump,
+            other: &'bump Self,
+        ) -> FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn>;
+    }
+
+    /// The arena-allocated counterpart of [`Field`](crate::Field).
+    #[derive(Default)]
+    pub enum FieldIn<'bump, V, K, A>
+    where
+        K: Apply<V>,
+        A: Apply<V>,
+    {
+        #[default]
+        None,
+        Set(V),
+        Changes(K),
+        Actions(BumpVec<'bump, A>),
+    }
+
+    impl<'bump, V, K, A> FieldIn<'bump, V, K, A>
+    where
+        K: Apply<V>,
+        A: Apply<V>,
+    {
+        pub fn is_none(&self) -> bool {
+            matches!(self, FieldIn::None)
+        }
+    }
+
+    impl<'bump, V, K, A> Debug for FieldIn<'bump, V, K, A>
+    where
+        V: Diff,
+        K: Debug + Apply<V>,
+        A: Debug + Apply<V>,
+    {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                FieldIn::None => f.write_str("None"),
+                FieldIn::Set(value) => f
+                    .debug_tuple("Set")
+                    .field(redacted_if(V::SENSITIVE, value))
+                    .finish(),
+                FieldIn::Changes(changeset) => f.debug_tuple("Changes").field(changeset).finish(),
+                FieldIn::Actions(actions) => f.debug_tuple("Actions").field(actions).finish(),
+            }
+        }
+    }
+
+    /// Shorthand for the [`FieldIn`] produced by `V::changeset_in`.
+    pub type FieldOf<'bump, V> =
+        FieldIn<'bump, V, <V as DiffIn<'bump>>::ChangesetIn, <V as DiffIn<'bump>>::ActionIn>;
+
+    impl<'bump, V, K, A> Apply<V> for FieldIn<'bump, V, K, A>
+    where
+        V: Diff,
+        K: Debug + Apply<V>,
+        A: Apply<V>,
+    {
+        fn apply(self, target: &mut V) {
+            match self {
+                FieldIn::None => {}
+                FieldIn::Set(value) => {
+                    *target = value;
+                }
+                FieldIn::Changes(changeset) => changeset.apply(target),
+                FieldIn::Actions(actions) => {
+                    actions.into_iter().for_each(|x| x.apply(target));
+                }
+            }
+        }
+    }
+
+    pub enum VecActionIn<'bump, T: Clone + DiffIn<'bump>> {
+        Set(usize, FieldOf<'bump, T>),
+        Truncate(usize),
+        /// Borrowed from the diffed sequence when produced by `changeset_in`,
+        /// and only cloned once applied.
+        Append(Cow<'bump, [T]>),
+    }
+
+    impl<'bump, T: Clone + DiffIn<'bump>> Debug for VecActionIn<'bump, T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                VecActionIn::Set(index, field) => {
+                    f.debug_tuple("Set").field(index).field(field).finish()
+                }
+                VecActionIn::Truncate(len) => f.debug_tuple("Truncate").field(len).finish(),
+                VecActionIn::Append(items) => f
+                    .debug_tuple("Append")
+                    .field(redacted_if(T::SENSITIVE, items))
+                    .finish(),
+            }
+        }
+    }
+
+    impl<'bump, T: Clone + DiffIn<'bump>> Apply<Vec<T>> for VecActionIn<'bump, T> {
+        fn apply(self, target: &mut Vec<T>) {
+            use VecActionIn::*;
+
+            match self {
+                Set(index, field) => {
+                    field.apply(&mut target[index]);
+                }
+                Truncate(len) => target.truncate(len),
+                Append(Cow::Borrowed(items)) => target.extend_from_slice(items),
+                Append(Cow::Owned(mut items)) => target.append(&mut items),
+            }
+        }
+    }
+
+    impl<'bump, T> DiffIn<'bump> for Vec<T>
+    where
+        T: Clone + PartialEq + DiffIn<'bump>,
+    {
+        type ChangesetIn = ();
+        type ActionIn = VecActionIn<'bump, T>;
+
+        fn changeset_in(
+            &self,
+            arena: &'bump Bump,
+            other: &'bump Self,
+        ) -> FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+            if self == other {
+                return FieldIn::None;
+            }
+
+            let mut changes = BumpVec::new_in(arena);
+
+            let min = std::cmp::min(self.len(), other.len());
+
+            for_each_candidate(&self[..min], &other[..min], 0, |i, a, b| {
+                match a.changeset_in(arena, b) {
+                    FieldIn::None => {}
+                    changeset => changes.push(VecActionIn::Set(i, changeset)),
+                }
+            });
+
+            if self.len() > other.len() {
+                changes.push(VecActionIn::Truncate(other.len()));
+            } else if self.len() < other.len() {
+                changes.push(VecActionIn::Append(Cow::Borrowed(&other[min..])));
+            }
+
+            FieldIn::Actions(changes)
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum OptionChangesetIn<'bump, T: DiffIn<'bump>> {
+        SomeChangeset(FieldOf<'bump, T>),
+    }
+
+    impl<'bump, T: DiffIn<'bump>> Apply<Option<T>> for OptionChangesetIn<'bump, T> {
+        fn apply(self, target: &mut Option<T>) {
+            match (self, target) {
+                (OptionChangesetIn::SomeChangeset(field), Some(v)) => field.apply(v),
+                (_, None) => unreachable!("This is a logic error."),
+            }
+        }
+    }
+
+    impl<'bump, T> DiffIn<'bump> for Option<T>
+    where
+        T: Clone + PartialEq + DiffIn<'bump>,
+    {
+        type ChangesetIn = OptionChangesetIn<'bump, T>;
+        type ActionIn = ();
+
+        fn changeset_in(
+            &self,
+            arena: &'bump Bump,
+            other: &'bump Self,
+        ) -> FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+            if self == other {
+                return FieldIn::None;
+            }
+
+            match (self, other) {
+                (Some(a), Some(b)) => {
+                    FieldIn::Changes(OptionChangesetIn::SomeChangeset(a.changeset_in(arena, b)))
+                }
+                (_, v) => FieldIn::Set(v.to_owned()),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ResultChangesetIn<'bump, T: DiffIn<'bump>, E: DiffIn<'bump>> {
+        OkChangeset(FieldOf<'bump, T>),
+        ErrChangeset(FieldOf<'bump, E>),
+    }
+
+    impl<'bump, T, E> Apply<Result<T, E>> for ResultChangesetIn<'bump, T, E>
+    where
+        T: DiffIn<'bump>,
+        E: DiffIn<'bump>,
+    {
+        fn apply(self, target: &mut Result<T, E>) {
+            use ResultChangesetIn::*;
+
+            match (self, target) {
+                (OkChangeset(x), Ok(inner)) => x.apply(inner),
+                (ErrChangeset(x), Err(inner)) => x.apply(inner),
+                _ => unreachable!("Logic error"),
+            }
+        }
+    }
+
+    impl<'bump, T, E> DiffIn<'bump> for Result<T, E>
+    where
+        T: Clone + PartialEq + DiffIn<'bump>,
+        E: Clone + PartialEq + DiffIn<'bump>,
+    {
+        type ChangesetIn = ResultChangesetIn<'bump, T, E>;
+        type ActionIn = ();
+
+        fn changeset_in(
+            &self,
+            arena: &'bump Bump,
+            other: &'bump Self,
+        ) -> FieldIn<'bump, Self, Self::ChangesetIn, Self::ActionIn> {
+            if self == other {
+                return FieldIn::None;
+            }
+
+            let changes = match (self, other) {
+                (Ok(a), Ok(b)) => ResultChangesetIn::OkChangeset(a.changeset_in(arena, b)),
+                (Err(a), Err(b)) => ResultChangesetIn::ErrChangeset(a.changeset_in(arena, b)),
+                (_, v) => return FieldIn::Set(v.to_owned()),
+            };
+
+            FieldIn::Changes(changes)
+        }
+    }
+
+    // Written by hand rather than derived so that variant names and indices
+    // match the owned changesets, which have variants the arena ones lack.
+    #[cfg(feature = "serde")]
+    mod ser {
+        use super::{DiffIn, FieldIn, FieldOf, OptionChangesetIn, ResultChangesetIn, VecActionIn};
+        use crate::Apply;
+        use serde::ser::{Serialize, SerializeTupleVariant, Serializer};
+
+        impl<'bump, V, K, A> Serialize for FieldIn<'bump, V, K, A>
+        where
+            V: Serialize,
+            K: Serialize + Apply<V>,
+            A: Serialize + Apply<V>,
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    FieldIn::None => serializer.serialize_unit_variant("Field", 0, "None"),
+                    FieldIn::Set(value) => {
+                        serializer.serialize_newtype_variant("Field", 1, "Set", value)
+                    }
+                    FieldIn::Changes(changes) => {
+                        serializer.serialize_newtype_variant("Field", 2, "Changes", changes)
+                    }
+                    FieldIn::Actions(actions) => serializer.serialize_newtype_variant(
+                        "Field",
+                        3,
+                        "Actions",
+                        actions.as_slice(),
+                    ),
+                }
+            }
+        }
+
+        impl<'bump, T> Serialize for VecActionIn<'bump, T>
+        where
+            T: Clone + Serialize + DiffIn<'bump>,
+            FieldOf<'bump, T>: Serialize,
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    VecActionIn::Set(index, field) => {
+                        let mut variant =
+                            serializer.serialize_tuple_variant("VecAction", 0, "Set", 2)?;
+                        variant.serialize_field(index)?;
+                        variant.serialize_field(field)?;
+                        variant.end()
+                    }
+                    VecActionIn::Truncate(len) => {
+                        serializer.serialize_newtype_variant("VecAction", 2, "Truncate", len)
+                    }
+                    VecActionIn::Append(items) => {
+                        serializer.serialize_newtype_variant("VecAction", 3, "Append", &**items)
+                    }
+                }
+            }
+        }
+
+        impl<'bump, T> Serialize for OptionChangesetIn<'bump, T>
+        where
+            T: DiffIn<'bump>,
+            FieldOf<'bump, T>: Serialize,
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    OptionChangesetIn::SomeChangeset(field) => serializer
+                        .serialize_newtype_variant("OptionChangeset", 1, "SomeChangeset", field),
+                }
+            }
+        }
+
+        impl<'bump, T, E> Serialize for ResultChangesetIn<'bump, T, E>
+        where
+            T: DiffIn<'bump>,
+            E: DiffIn<'bump>,
+            FieldOf<'bump, T>: Serialize,
+            FieldOf<'bump, E>: Serialize,
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    ResultChangesetIn::OkChangeset(field) => serializer.serialize_newtype_variant(
+                        "ResultChangeset",
+                        0,
+                        "OkChangeset",
+                        field,
+                    ),
+                    ResultChangesetIn::ErrChangeset(field) => serializer.serialize_newtype_variant(
+                        "ResultChangeset",
+                        1,
+                        "ErrChangeset",
+                        field,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Structured `tracing` events for applied changes.
+///
+/// Applying a derived changeset emits an event for each field it modifies,
+/// with the field's `path` from the value being applied to (such as
+/// `players[2].score`) and its Debug-formatted `old` and `new` values. Events
+/// use the deriving type's module as their target and the `TRACE` level,
+/// which can be overridden per type with
+/// `#[diff(trace(target = "...", level = "debug"))]`.
+#[cfg(feature = "tracing")]
+pub mod trace {
+    use std::cell::RefCell;
+    use std::fmt::Write;
+
+    #[doc(hidden)]
+    pub use tracing;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Segment {
+        Field(&'static str),
+        Index(usize),
+    }
+
+    thread_local! {
+        static PATH: RefCell<Vec<Segment>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Appends `segment` to the current path until the guard is dropped.
+    pub fn enter(segment: Segment) -> PathGuard {
+        PATH.with(|path| path.borrow_mut().push(segment));
+        PathGuard(())
+    }
+
+    /// The path of the field currently being applied on this thread.
+    pub fn path() -> String {
+        PATH.with(|path| {
+            let mut out = String::new();
+
+            for segment in path.borrow().iter() {
+                match segment {
+                    Segment::Field(name) if out.is_empty() => out.push_str(name),
+                    Segment::Field(name) => {
+                        out.push('.');
+                        out.push_str(name);
+                    }
+                    Segment::Index(index) => {
+                        let _ = write!(out, "[{}]", index);
+                    }
+                }
+            }
+
+            out
+        })
+    }
+
+    /// Whether `value` must be left out of events, as it holds sensitive
+    /// fields.
+    pub fn is_sensitive<T: crate::Diff>(_value: &T) -> bool {
+        T::SENSITIVE
+    }
+
+    pub struct PathGuard(());
+
+    impl Drop for PathGuard {
+        fn drop(&mut self) {
+            PATH.with(|path| path.borrow_mut().pop());
+        }
+    }
+}
+
+/// Schema versioning for serialized changesets.
+///
+/// Types deriving with `#[diff(serde)]` get a changeset that tolerates added
+/// and removed fields: fields missing from the input decode as `Field::None`
+/// and unknown fields are skipped. When a change needs more than that, such as
+/// a field whose meaning changed, give the type a version with
+/// `#[diff(version = 2, migrate = "path::to::fn")]` and send changesets
+/// wrapped in [`Versioned`]. Changesets from any other version are passed to
+/// the migration hook before they are returned.
+#[cfg(feature = "serde")]
+pub mod schema {
+    use super::{Canonicalize, Diff, Field};
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[doc(hidden)]
+    pub use serde;
+
+    pub trait Schema: Diff {
+        /// The current version of the type's changeset layout.
+        const VERSION: u32;
+
+        /// Converts a changeset written by a peer at version `from`.
+        fn migrate(_from: u32, _changes: &mut Field<Self, Self::Changeset, Self::Action>) {}
+    }
+
+    /// A changeset tagged with the schema version it was produced at.
+    ///
+    /// An absent tag decodes as version 0.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Versioned<C> {
+        #[serde(default)]
+        pub version: u32,
+        pub changes: C,
+    }
+
+    impl<T: Schema> Versioned<Field<T, T::Changeset, T::Action>> {
+        pub fn new(changes: Field<T, T::Changeset, T::Action>) -> Self {
+            Versioned {
+                version: T::VERSION,
+                changes,
+            }
+        }
+
+        /// Returns the changeset, migrated to the current version if needed.
+        pub fn into_current(self) -> Field<T, T::Changeset, T::Action> {
+            let mut changes = self.changes;
+
+            if self.version != T::VERSION {
+                T::migrate(self.version, &mut changes);
+            }
+
+            changes
+        }
+    }
+
+    /// A canonicalized changeset that serializes the entries of every map it
+    /// holds sorted by key, so that identical changes encode to identical
+    /// bytes on every machine.
+    ///
+    /// Maps are sorted by their keys' serialized form, which matches the
+    /// keys' own ordering for strings and integers. Floats are sorted with
+    /// `total_cmp` and keys of different kinds by kind, so every map has a
+    /// single order.
+    #[derive(Debug)]
+    pub struct Canonical<T>(T);
+
+    impl<T: Canonicalize> Canonical<T> {
+        pub fn new(mut changes: T) -> Self {
+            changes.canonicalize();
+            Canonical(changes)
+        }
+    }
+
+    impl<T> Canonical<T> {
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    impl<T: Serialize> Serialize for Canonical<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let content = self
+                .0
+                .serialize(content::ContentSerializer {
+                    human_readable: serializer.is_human_readable(),
+                })
+                .map_err(S::Error::custom)?;
+
+            content.serialize(serializer)
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for Canonical<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            T::deserialize(deserializer).map(Canonical)
+        }
+    }
+
+    /// A buffered copy of a value's serialized form, with map entries sorted
+    /// as they are collected.
+    pub(crate) mod content;
+}
+
+#[cfg(feature = "serde")]
+pub use schema::{Canonical, Schema, Versioned};
+
+/// TypeScript definitions for changesets, backed by `ts-rs`.
+///
+/// Types deriving with `#[diff(ts)]` get a `TS` impl for their changeset, so
+/// `FooChangeset::export_all()` writes its definition along with those of the
+/// changeset types it refers to. The definitions describe the serde encoding
+/// of the changeset.
+#[cfg(feature = "ts")]
+pub mod ts {
+    use super::hashed::{Hashed, HashedChangeset};
+    use super::types::{
+        HashMapChangeset, HashSetChangeset, MapAction, OptionChangeset, ResultChangeset, SetAction,
+        VecAction, VecChangeset,
+    };
+    use super::{Apply, Diff, Field};
+
+    #[doc(hidden)]
+    pub use ts_rs;
+
+    pub use ts_rs::TS;
+
+    /// Stand-ins for the changeset types, with the associated types of
+    /// `Diff` spelled out as generic parameters so `ts-rs` can declare them.
+    #[doc(hidden)]
+    pub mod mirror {
+        use ts_rs::TS;
+
+        #[derive(TS)]
+        pub enum Field<V, K, A> {
+            None,
+            Set(V),
+            Changes(K),
+            Actions(Vec<A>),
+            Redacted,
+        }
+
+        #[derive(TS)]
+        pub enum VecAction<T, C, A> {
+            Set(usize, Field<T, C, A>),
+            Push(T),
+            Truncate(usize),
+            Append(Vec<T>),
+            Insert(usize, T),
+            Remove(usize),
+        }
+
+        #[derive(TS)]
+        pub struct VecChangeset<T, C, A>(pub Field<T, C, A>);
+
+        #[derive(TS)]
+        pub enum OptionChangeset<T, C, A> {
+            NoneChangeset(Field<(), (), ()>),
+            SomeChangeset(Field<T, C, A>),
+        }
+
+        #[derive(TS)]
+        pub enum ResultChangeset<T, TC, TA, E, EC, EA> {
+            OkChangeset(Field<T, TC, TA>),
+            ErrChangeset(Field<E, EC, EA>),
+        }
+
+        #[derive(TS)]
+        pub enum MapAction<K, V, C, A> {
+            Insert(K, V),
+            Remove(K),
+            Set(K, Field<V, C, A>),
+        }
+
+        #[derive(TS)]
+        pub struct HashMapChangeset<K, V, C, A>(pub Vec<MapAction<K, V, C, A>>);
+
+        #[derive(TS)]
+        pub enum SetAction<T> {
+            Insert(T),
+            Remove(T),
+        }
+
+        #[derive(TS)]
+        pub struct HashSetChangeset<T>(pub Vec<SetAction<T>>);
+
+        #[derive(TS)]
+        pub struct Hashed<T> {
+            pub value: T,
+            #[ts(type = "string")]
+            pub hash: u64,
+        }
+
+        #[derive(TS)]
+        pub struct HashedChangeset<T, C, A> {
+            #[ts(type = "string")]
+            pub hash: u64,
+            pub changes: Field<T, C, A>,
+        }
+    }
+
+    macro_rules! impl_mirrored {
+        (as $mirror:ty: $($impl:tt)*) => {
+            $($impl)* {
+                type WithoutGenerics = <$mirror as TS>::WithoutGenerics;
+                type OptionInnerType = Self;
+
+                fn ident() -> String {
+                    <$mirror as TS>::ident()
+                }
+
+                fn name() -> String {
+                    <$mirror as TS>::name()
+                }
+
+                fn inline() -> String {
+                    <$mirror as TS>::inline()
+                }
+
+                fn inline_flattened() -> String {
+                    <$mirror as TS>::inline_flattened()
+                }
+
+                fn visit_dependencies(v: &mut impl ts_rs::TypeVisitor)
+                where
+                    Self: 'static,
+                {
+                    <$mirror as TS>::visit_dependencies(v);
+                }
+
+                fn visit_generics(v: &mut impl ts_rs::TypeVisitor)
+                where
+                    Self: 'static,
+                {
+                    <$mirror as TS>::visit_generics(v);
+                }
+
+                fn decl() -> String {
+                    <$mirror as TS>::decl()
+                }
+
+                fn decl_concrete() -> String {
+                    <$mirror as TS>::decl_concrete()
+                }
+
+                fn output_path() -> Option<std::path::PathBuf> {
+                    <$mirror as TS>::output_path()
+                }
+            }
+        };
+    }
+
+    impl_mirrored!(as mirror::Field<V, K, A>:
+        impl<V: TS, K: TS + Apply<V>, A: TS + Apply<V>> TS for Field<V, K, A>);
+
+    impl_mirrored!(as mirror::VecAction<T, T::Changeset, T::Action>:
+        impl<T: Diff + TS> TS for VecAction<T>
+        where
+            T::Changeset: TS,
+            T::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::VecChangeset<T, T::Changeset, T::Action>:
+        impl<T: Diff + TS> TS for VecChangeset<T>
+        where
+            T::Changeset: TS,
+            T::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::OptionChangeset<T, T::Changeset, T::Action>:
+        impl<T: Diff + TS> TS for OptionChangeset<T>
+        where
+            T::Changeset: TS,
+            T::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::ResultChangeset<T, T::Changeset, T::Action, E, E::Changeset, E::Action>:
+        impl<T: Diff + TS, E: Diff + TS> TS for ResultChangeset<T, E>
+        where
+            T::Changeset: TS,
+            T::Action: TS,
+            E::Changeset: TS,
+            E::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::MapAction<K, V, V::Changeset, V::Action>:
+        impl<K: TS, V: Diff + TS> TS for MapAction<K, V>
+        where
+            V::Changeset: TS,
+            V::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::HashMapChangeset<K, V, V::Changeset, V::Action>:
+        impl<K: TS, V: Diff + TS> TS for HashMapChangeset<K, V>
+        where
+            V::Changeset: TS,
+            V::Action: TS,
+    );
+
+    impl_mirrored!(as mirror::SetAction<T>: impl<T: TS> TS for SetAction<T>);
+
+    impl_mirrored!(as mirror::HashSetChangeset<T>: impl<T: TS> TS for HashSetChangeset<T>);
+
+    impl_mirrored!(as mirror::Hashed<T>: impl<T: TS> TS for Hashed<T>);
+
+    impl_mirrored!(as mirror::HashedChangeset<T, T::Changeset, T::Action>:
+        impl<T: Diff + TS> TS for HashedChangeset<T>
+        where
+            T::Changeset: TS,
+            T::Action: TS,
+    );
+}
+
+#[cfg(feature = "bevy_reflect")]
+pub use reflect::{diff_reflect, ReflectChangeset};
+
+/// Diffing of values known only through `bevy_reflect`, for types that are
+/// registered at runtime rather than deriving [`Diff`](super::Diff).
+#[cfg(feature = "bevy_reflect")]
+pub mod reflect {
+    use bevy_reflect::{
+        ApplyError, ListInfo, MapInfo, PartialReflect, ReflectCloneError, ReflectKind, ReflectMut,
+        ReflectRef, Type, TypeInfo,
+    };
+    use std::fmt;
+
+    /// The changes that turn one reflected value into another.
+    ///
+    /// Structs, tuples, lists and maps are compared field by field, element
+    /// by element or key by key. Enums, sets and opaque values that differ are
+    /// replaced as a whole.
+    #[derive(Debug)]
+    pub enum ReflectChangeset {
+        /// Replaces the value as a whole.
+        Set(Box<dyn PartialReflect>),
+        /// Changes to the named fields of a struct.
+        Struct(Vec<(String, ReflectChangeset)>),
+        /// Changes to the fields of a tuple or tuple struct, or the elements
+        /// of an array, by index, along with which of those kinds it is.
+        Indexed(ReflectKind, Vec<(usize, ReflectChangeset)>),
+        List(Vec<ListAction>),
+        Map(Vec<MapAction>),
+    }
+
+    #[derive(Debug)]
+    pub enum ListAction {
+        Set(usize, ReflectChangeset),
+        Push(Box<dyn PartialReflect>),
+        Truncate(usize),
+    }
+
+    #[derive(Debug)]
+    pub enum MapAction {
+        Insert(Box<dyn PartialReflect>, Box<dyn PartialReflect>),
+        Remove(Box<dyn PartialReflect>),
+        Set(Box<dyn PartialReflect>, ReflectChangeset),
+    }
+
+    /// Why a [`ReflectChangeset`] could not be applied to a value.
+    #[derive(Debug)]
+    pub enum ReflectApplyError {
+        /// The target is a different kind of value than the changeset was
+        /// made for, such as a list where a struct was expected.
+        MismatchedKinds {
+            expected: ReflectKind,
+            found: ReflectKind,
+        },
+        MissingField(String),
+        MissingIndex(usize),
+        MissingKey(String),
+        Apply(ApplyError),
+        /// A value to insert is opaque and cannot be cloned through
+        /// reflection.
+        Clone(ReflectCloneError),
+    }
+
+    impl fmt::Display for ReflectApplyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ReflectApplyError::MismatchedKinds { expected, found } => {
+                    write!(f, "expected a {}, found a {}", expected, found)
+                }
+                ReflectApplyError::MissingField(name) => write!(f, "no field named `{}`", name),
+                ReflectApplyError::MissingIndex(index) => {
+                    write!(f, "no element at index {}", index)
+                }
+                ReflectApplyError::MissingKey(key) => write!(f, "no entry for key {}", key),
+                ReflectApplyError::Apply(err) => err.fmt(f),
+                ReflectApplyError::Clone(err) => err.fmt(f),
+            }
+        }
+    }
+
+    impl std::error::Error for ReflectApplyError {}
+
+    impl From<ApplyError> for ReflectApplyError {
+        fn from(err: ApplyError) -> Self {
+            ReflectApplyError::Apply(err)
+        }
+    }
+
+    impl From<ReflectCloneError> for ReflectApplyError {
+        fn from(err: ReflectCloneError) -> Self {
+            ReflectApplyError::Clone(err)
+        }
+    }
+
+    /// The changes that turn `a` into `b`, or `None` if they are equal.
+    ///
+    /// Fails if a value that has to be copied into the changeset is opaque
+    /// and cannot be cloned through reflection.
+    pub fn diff_reflect(
+        a: &dyn PartialReflect,
+        b: &dyn PartialReflect,
+    ) -> Result<Option<ReflectChangeset>, ReflectCloneError> {
+        if a.reflect_partial_eq(b) == Some(true) {
+            return Ok(None);
+        }
+
+        let changeset = match (a.reflect_ref(), b.reflect_ref()) {
+            (ReflectRef::Struct(a), ReflectRef::Struct(b)) if a.field_len() == b.field_len() => {
+                let mut fields = vec![];
+
+                for (index, value) in b.iter_fields().enumerate() {
+                    let name = b.name_at(index).unwrap();
+                    let old = match a.field(name) {
+                        Some(old) => old,
+                        None => {
+                            return Ok(Some(ReflectChangeset::Set(copy(b.as_partial_reflect())?)))
+                        }
+                    };
+
+                    if let Some(changes) = diff_reflect(old, value)? {
+                        fields.push((name.to_string(), changes));
+                    }
+                }
+
+                ReflectChangeset::Struct(fields)
+            }
+            (ReflectRef::TupleStruct(a), ReflectRef::TupleStruct(b))
+                if a.field_len() == b.field_len() =>
+            {
+                diff_indexed(ReflectKind::TupleStruct, a.iter_fields(), b.iter_fields())?
+            }
+            (ReflectRef::Tuple(a), ReflectRef::Tuple(b)) if a.field_len() == b.field_len() => {
+                diff_indexed(ReflectKind::Tuple, a.iter_fields(), b.iter_fields())?
+            }
+            (ReflectRef::Array(a), ReflectRef::Array(b)) if a.len() == b.len() => {
+                diff_indexed(ReflectKind::Array, a.iter(), b.iter())?
+            }
+            (ReflectRef::List(a), ReflectRef::List(b)) => {
+                let mut actions = vec![];
+
+                for (index, (old, new)) in a.iter().zip(b.iter()).enumerate() {
+                    if let Some(changes) = diff_reflect(old, new)? {
+                        actions.push(ListAction::Set(index, changes));
+                    }
+                }
+
+                if a.len() > b.len() {
+                    actions.push(ListAction::Truncate(b.len()));
+                } else {
+                    for value in b.iter().skip(a.len()) {
+                        actions.push(ListAction::Push(copy(value)?));
+                    }
+                }
+
+                ReflectChangeset::List(actions)
+            }
+            (ReflectRef::Map(a), ReflectRef::Map(b)) => {
+                let mut actions = vec![];
+
+                for (key, old) in a.iter() {
+                    match b.get(key) {
+                        Some(new) => {
+                            if let Some(changes) = diff_reflect(old, new)? {
+                                actions.push(MapAction::Set(copy(key)?, changes));
+                            }
+                        }
+                        None => actions.push(MapAction::Remove(copy(key)?)),
+                    }
+                }
+
+                for (key, new) in b.iter() {
+                    if a.get(key).is_none() {
+                        actions.push(MapAction::Insert(copy(key)?, copy(new)?));
+                    }
+                }
+
+                ReflectChangeset::Map(actions)
+            }
+            _ => ReflectChangeset::Set(copy(b)?),
+        };
+
+        if changeset.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(changeset))
+        }
+    }
+
+    fn diff_indexed<'a>(
+        kind: ReflectKind,
+        a: impl Iterator<Item = &'a dyn PartialReflect>,
+        b: impl Iterator<Item = &'a dyn PartialReflect>,
+    ) -> Result<ReflectChangeset, ReflectCloneError> {
+        let mut fields = vec![];
+
+        for (index, (old, new)) in a.zip(b).enumerate() {
+            if let Some(changes) = diff_reflect(old, new)? {
+                fields.push((index, changes));
+            }
+        }
+
+        Ok(ReflectChangeset::Indexed(kind, fields))
+    }
+
+    /// Copies `value` with `to_dynamic`, which panics on opaque values that
+    /// cannot be cloned through reflection, so those are checked for first.
+    fn copy(value: &dyn PartialReflect) -> Result<Box<dyn PartialReflect>, ReflectCloneError> {
+        check_clone(value)?;
+        Ok(value.to_dynamic())
+    }
+
+    /// Checks that `value` represents `ty`, the type a concrete target
+    /// converts it into, as those panic on values they cannot convert.
+    /// Dynamic targets have no type and take any value.
+    fn check_type(value: &dyn PartialReflect, ty: Option<&Type>) -> Result<(), ReflectApplyError> {
+        let ty = match ty {
+            Some(ty) => ty,
+            None => return Ok(()),
+        };
+
+        match value.get_represented_type_info() {
+            Some(info) if info.type_id() == ty.id() => Ok(()),
+            _ => Err(ReflectApplyError::Apply(ApplyError::MismatchedTypes {
+                from_type: value.reflect_type_path().into(),
+                to_type: ty.path().into(),
+            })),
+        }
+    }
+
+    fn check_clone(value: &dyn PartialReflect) -> Result<(), ReflectCloneError> {
+        if let ReflectRef::Opaque(value) = value.reflect_ref() {
+            return value.reflect_clone().map(drop);
+        }
+
+        match value.reflect_ref() {
+            ReflectRef::Struct(value) => value.iter_fields().try_for_each(check_clone),
+            ReflectRef::TupleStruct(value) => value.iter_fields().try_for_each(check_clone),
+            ReflectRef::Tuple(value) => value.iter_fields().try_for_each(check_clone),
+            ReflectRef::List(value) => value.iter().try_for_each(check_clone),
+            ReflectRef::Array(value) => value.iter().try_for_each(check_clone),
+            ReflectRef::Map(value) => value.iter().try_for_each(|(key, value)| {
+                check_clone(key)?;
+                check_clone(value)
+            }),
+            ReflectRef::Set(value) => value.iter().try_for_each(check_clone),
+            ReflectRef::Enum(value) => value
+                .iter_fields()
+                .try_for_each(|field| check_clone(field.value())),
+            // Opaque values are handled above, and functions are copied
+            // without cloning.
+            _ => Ok(()),
+        }
+    }
+
+    impl ReflectChangeset {
+        fn is_empty(&self) -> bool {
+            match self {
+                ReflectChangeset::Set(_) => false,
+                ReflectChangeset::Struct(fields) => fields.is_empty(),
+                ReflectChangeset::Indexed(_, fields) => fields.is_empty(),
+                ReflectChangeset::List(actions) => actions.is_empty(),
+                ReflectChangeset::Map(actions) => actions.is_empty(),
+            }
+        }
+
+        /// Applies the changes to `target` through reflection.
+        ///
+        /// Changes are applied in order, so on error `target` keeps any that
+        /// came before the failing one.
+        pub fn apply(&self, target: &mut dyn PartialReflect) -> Result<(), ReflectApplyError> {
+            let found = target.reflect_kind();
+            let mismatch = |expected| ReflectApplyError::MismatchedKinds { expected, found };
+
+            match self {
+                ReflectChangeset::Set(value) => {
+                    let ty = target.get_represented_type_info().map(TypeInfo::ty);
+                    check_type(value.as_ref(), ty)?;
+                    target.try_apply(value.as_ref())?;
+                }
+                ReflectChangeset::Struct(fields) => {
+                    let target = match target.reflect_mut() {
+                        ReflectMut::Struct(target) => target,
+                        _ => return Err(mismatch(ReflectKind::Struct)),
+                    };
+
+                    for (name, changes) in fields {
+                        match target.field_mut(name) {
+                            Some(field) => changes.apply(field)?,
+                            None => return Err(ReflectApplyError::MissingField(name.clone())),
+                        }
+                    }
+                }
+                ReflectChangeset::Indexed(kind, fields) => {
+                    if found != *kind {
+                        return Err(mismatch(*kind));
+                    }
+
+                    let mut target = target.reflect_mut();
+
+                    for (index, changes) in fields {
+                        let field = match &mut target {
+                            ReflectMut::TupleStruct(target) => target.field_mut(*index),
+                            ReflectMut::Tuple(target) => target.field_mut(*index),
+                            ReflectMut::Array(target) => target.get_mut(*index),
+                            _ => return Err(mismatch(*kind)),
+                        };
+
+                        match field {
+                            Some(field) => changes.apply(field)?,
+                            None => return Err(ReflectApplyError::MissingIndex(*index)),
+                        }
+                    }
+                }
+                ReflectChangeset::List(actions) => {
+                    let target = match target.reflect_mut() {
+                        ReflectMut::List(target) => target,
+                        _ => return Err(mismatch(ReflectKind::List)),
+                    };
+
+                    for action in actions {
+                        match action {
+                            ListAction::Set(index, changes) => match target.get_mut(*index) {
+                                Some(element) => changes.apply(element)?,
+                                None => return Err(ReflectApplyError::MissingIndex(*index)),
+                            },
+                            ListAction::Push(value) => {
+                                let ty = target.get_represented_list_info().map(ListInfo::item_ty);
+                                check_type(value.as_ref(), ty.as_ref())?;
+                                target.push(copy(value.as_ref())?);
+                            }
+                            ListAction::Truncate(len) => {
+                                while target.len() > *len {
+                                    target.pop();
+                                }
+                            }
+                        }
+                    }
+                }
+                ReflectChangeset::Map(actions) => {
+                    let target = match target.reflect_mut() {
+                        ReflectMut::Map(target) => target,
+                        _ => return Err(mismatch(ReflectKind::Map)),
+                    };
+
+                    for action in actions {
+                        match action {
+                            MapAction::Insert(key, value) => {
+                                let info = target.get_represented_map_info();
+                                check_type(key.as_ref(), info.map(MapInfo::key_ty).as_ref())?;
+                                check_type(value.as_ref(), info.map(MapInfo::value_ty).as_ref())?;
+                                target.insert_boxed(copy(key.as_ref())?, copy(value.as_ref())?);
+                            }
+                            MapAction::Remove(key) => {
+                                target.remove(key.as_ref());
+                            }
+                            MapAction::Set(key, changes) => match target.get_mut(key.as_ref()) {
+                                Some(value) => changes.apply(value)?,
+                                None => {
+                                    return Err(ReflectApplyError::MissingKey(format!("{:?}", key)))
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Applies a field's changeset in derived impls, emitting an event for the
+/// change when the `tracing` feature is enabled. Events for sensitive fields,
+/// and for fields whose type holds one, carry only the path.
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_field {
+    (sensitive $target:expr, $level:expr, $name:expr, $changes:expr, $value:expr, $apply:expr) => {{
+        let changed = !$changes.is_none();
+        let _segment = $crate::trace::enter($crate::trace::Segment::Field($name));
+        $apply;
+        if changed {
+            $crate::trace::tracing::event!(
+                target: $target,
+                $level,
+                path = %$crate::trace::path(),
+                redacted = true,
+            );
+        }
+    }};
+    ($target:expr, $level:expr, $name:expr, $changes:expr, $value:expr, $apply:expr) => {{
+        if $crate::trace::is_sensitive(&$value) {
+            $crate::__trace_field!(sensitive $target, $level, $name, $changes, $value, $apply);
+        } else if !$changes.is_none()
+            && $crate::trace::tracing::event_enabled!(target: $target, $level)
+        {
+            let old = format!("{:?}", $value);
+            let _segment = $crate::trace::enter($crate::trace::Segment::Field($name));
+            $apply;
+            $crate::trace::tracing::event!(
+                target: $target,
+                $level,
+                path = %$crate::trace::path(),
+                old = %old,
+                new = ?$value,
+            );
+        } else {
+            let _segment = $crate::trace::enter($crate::trace::Segment::Field($name));
+            $apply;
+        }
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_field {
+    (sensitive $target:expr, $level:expr, $name:expr, $changes:expr, $value:expr, $apply:expr) => {
+        $apply
+    };
+    ($target:expr, $level:expr, $name:expr, $changes:expr, $value:expr, $apply:expr) => {
+        $apply
+    };
+}
+
+#[cfg(test)]
+#[allow(dead_code, clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+
+    type SetField<A, B> = Field<A, B, ()>;
+
+    #[derive(Debug, Default, PartialEq, Clone)]
+    enum SomeEnum {
+        #[default]
+        None,
+        Field1(String),
+        Field2(u32),
+        Field3(Bar),
+        Field4(u16, u16),
+    }
+
+    #[derive(Debug)]
+    enum SomeEnumChangeset {
+        None(SetField<(), ()>),
+        Field1(SetField<String, StringChangeset>),
+        Field2(SetField<u32, U32Changeset>),
+        Field3(SetField<Bar, BarChangeset>),
+        Field4(SetField<u16, U16Changeset>, SetField<u16, U16Changeset>),
+    }
+
+    impl Apply<SomeEnum> for SomeEnumChangeset {
+        fn apply(self, target: &mut SomeEnum) {
+            use SomeEnumChangeset::*;
+
+            match self {
+                None(_) => {
+                    *target = SomeEnum::None;
+                }
+                Field1(field) => match target {
+                    SomeEnum::Field1(target) => field.apply(target),
+                    _ => unreachable!("logic error"),
+                },
+                Field2(field) => match target {
+                    SomeEnum::Field2(target) => field.apply(target),
+                    _ => unreachable!("logic error"),
+                },
+                Field3(field) => match target {
+                    SomeEnum::Field3(target) => field.apply(target),
+                    _ => unreachable!("logic error"),
+                },
+                Field4(a, b) => match target {
+                    SomeEnum::Field4(t1, t2) => {
+                        a.apply(t1);
+                        b.apply(t2);
+                    }
+                    _ => unreachable!("logic error"),
+                },
+            }
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Foo {
+        field_a: u32,
+        field_b: String,
+        enumer: SomeEnum,
+        bar1: Bar,
+        bar: Option<Bar>,
+        vec: Vec<String>,
+    }
+
+    impl Diff for SomeEnum {
+        type Changeset = SomeEnumChangeset;
+        type Action = ();
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
+        where
+            Self: Sized,
+        {
+            if self == other {
+                return Field::None;
+            }
+
+            use SomeEnum::*;
+
+            let changes = match (self, other) {
+                (None, None) => SomeEnumChangeset::None(().changeset(&())),
+                (Field1(a), Field1(b)) => SomeEnumChangeset::Field1(a.changeset(b)),
+                (Field2(a), Field2(b)) => SomeEnumChangeset::Field2(a.changeset(b)),
+                (Field3(a), Field3(b)) => SomeEnumChangeset::Field3(a.changeset(b)),
+                (Field4(a1, a2), Field4(b1, b2)) => {
+                    SomeEnumChangeset::Field4(a1.changeset(b1), a2.changeset(b2))
+                }
+                (_, v) => return Field::Set(v.to_owned()),
+            };
+
+            Field::Changes(changes)
+        }
+    }
+    impl Diff for Foo {
+        type Changeset = FooChangeset;
+        type Action = ();
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
+        where
+            Self: Sized,
+        {
+            if self == other {
+                return Field::None;
+            }
+
+            let mut changes = Self::Changeset::default();
+
+            changes.field_a = self.field_a.changeset(&other.field_a);
+            changes.field_b = self.field_b.changeset(&other.field_b);
+            changes.enumer = self.enumer.changeset(&other.enumer);
+            changes.bar = self.bar.changeset(&other.bar);
+            changes.bar1 = self.bar1.changeset(&other.bar1);
+            changes.vec = self.vec.changeset(&other.vec);
+
+            Field::Changes(changes)
+        }
+    }
+
+    impl Diff for Bar {
+        type Changeset = BarChangeset;
+        type Action = ();
+
+        fn changeset(&self, other: &Self) -> Field<Self, Self::Changeset, Self::Action>
+        where
+            Self: Sized,
+        {
+            if self == other {
+                return Field::None;
+            }
+
+            let mut changes = Self::Changeset::default();
+
+            if self.field_d != other.field_d {
+                changes.field_d = Field::Set(other.field_d.to_owned())
+            }
+
+            Field::Changes(changes)
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Bar {
+        field_d: String,
+    }
+
+    #[derive(Debug, Default)]
+    struct FooChangeset {
+        field_a: SetField<u32, U32Changeset>,
+        field_b: SetField<String, StringChangeset>,
+        enumer: SetField<SomeEnum, SomeEnumChangeset>,
+        bar1: SetField<Bar, BarChangeset>,
+        bar: SetField<Option<Bar>, OptionChangeset<Bar>>,
+        vec: Field<Vec<String>, VecChangeset<String>, VecAction<String>>,
+    }
+
+    impl Apply<Foo> for FooChangeset {
+        fn apply(self, target: &mut Foo) {
+            self.field_a.apply(&mut target.field_a);
+            self.field_b.apply(&mut target.field_b);
+            self.enumer.apply(&mut target.enumer);
+            self.bar1.apply(&mut target.bar1);
+            self.bar.apply(&mut target.bar);
+            self.vec.apply(&mut target.vec);
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct BarChangeset {
+        field_d: SetField<String, StringChangeset>,
+    }
+
+    impl Apply<Bar> for BarChangeset {
+        fn apply(self, target: &mut Bar) {
+            self.field_d.apply(&mut target.field_d);
+        }
+    }
+
+    #[test]
+    fn basic() {
+        println!("{:?}", Foo::default().changeset(&Foo::default()));
+
+        let mut g = Foo::default();
+        g.enumer = SomeEnum::Field4(22, 44);
+        g.bar = Some(Bar {
+            field_d: "Mongol".into(),
+        });
+        g.vec = vec!["A".into(), "X".into(), "C".into()];
+
+        let mut f = Foo::default();
+        f.field_a = 123;
+        f.field_b = "ahahah".into();
+        f.enumer = SomeEnum::None;
+        f.bar = Some(Bar {
+            field_d: "Hello".into(),
+        });
+        f.vec = vec!["A".into(), "B".into(), "C".into(), "D".into(), "D".into()];
+
+        // f.bar = Some(Bar { field_d: "AOWIJEWIOAJE".into() });
+        let changeset = g.changeset(&f);
+        println!("{:#?}", &changeset);
+
+        changeset.apply(&mut g);
+        println!("{:#?}", &g);
+
+        assert_eq!(&f, &g);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_changeset() {
+        let a: Vec<u32> = (0..10_000).collect();
+        let mut b = a.clone();
+        b[17] = 0;
+        b[9_000] = 1;
+        b.extend(0..5);
+
+        let serial = a.changeset(&b);
+        let parallel = a.par_changeset(&b);
+        assert_eq!(format!("{:?}", serial), format!("{:?}", parallel));
+
+        let mut target = a.clone();
+        parallel.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_changeset_map() {
+        use std::collections::HashMap;
+
+        let a: HashMap<u32, String> = (0..10_000).map(|x| (x, x.to_string())).collect();
+        let mut b = a.clone();
+        b.insert(17, "changed".into());
+        b.insert(10_001, "added".into());
+        b.remove(&9_000);
+
+        let serial = a.changeset(&b);
+        let parallel = a.par_changeset(&b);
+        assert_eq!(format!("{:?}", serial), format!("{:?}", parallel));
+
+        let mut target = a.clone();
+        parallel.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn block_compare() {
+        let a: Vec<u8> = (0..1000).map(|x| x as u8).collect();
+        let mut b = a.clone();
+        for &i in &[0, 63, 64, 999] {
+            b[i] = b[i].wrapping_add(1);
+        }
+
+        let changeset = a.changeset(&b);
+        let indices = match &changeset {
+            Field::Actions(actions) => actions
+                .iter()
+                .map(|x| match x {
+                    VecAction::Set(i, _) => *i,
+                    x => panic!("unexpected action {:?}", x),
+                })
+                .collect::<Vec<_>>(),
+            x => panic!("unexpected changeset {:?}", x),
+        };
+        assert_eq!(indices, vec![0, 63, 64, 999]);
+
+        let mut target = a.clone();
+        changeset.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn inline_actions() {
+        let a = vec![1u32, 2, 3];
+        let b = vec![1u32, 5, 3, 4];
+
+        match a.changeset(&b) {
+            Field::Actions(actions) => {
+                assert_eq!(actions.len(), 2);
+                assert!(!actions.spilled());
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn arena_changeset() {
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        #[diff(arena)]
+        struct Frame {
+            tick: u64,
+            positions: Vec<f32>,
+            label: Option<String>,
+        }
+
+        let arena = arena::Bump::new();
+
+        let a = Frame {
+            tick: 1,
+            positions: vec![0.0, 1.0, 2.0],
+            label: Some("start".into()),
+        };
+        let b = Frame {
+            tick: 2,
+            positions: vec![0.0, 1.5, 2.0, 3.0],
+            label: Some("moving".into()),
+        };
+
+        let changeset = a.changeset_in(&arena, &b);
+
+        match &changeset {
+            arena::FieldIn::Changes(FrameChangesetIn {
+                positions: arena::FieldIn::Actions(actions),
+                ..
+            }) => match actions.last() {
+                Some(arena::VecActionIn::Append(std::borrow::Cow::Borrowed(tail))) => {
+                    assert_eq!(tail.as_ptr(), b.positions[3..].as_ptr());
+                }
+                x => panic!("unexpected action {:?}", x),
+            },
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        let mut target = a.clone();
+        changeset.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn streaming() {
+        let a: Vec<String> = vec!["A".into(), "B".into(), "C".into()];
+        let b: Vec<String> = vec!["A".into(), "X".into(), "C".into(), "D".into(), "E".into()];
+
+        let mut target = a.clone();
+        diff_streaming(a.iter().cloned(), b.iter().cloned(), |action| {
+            action.apply(&mut target)
+        });
+        assert_eq!(target, b);
+
+        let actions = DiffStream::new(b.clone(), a.clone()).collect::<Vec<_>>();
+        assert_eq!(actions.len(), 2);
+
+        let mut target = b.clone();
+        actions.into_iter().for_each(|x| x.apply(&mut target));
+        assert_eq!(target, a);
+    }
+
+    #[test]
+    fn encoded_size_hint() {
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(encoded_size)]
+        struct Packet {
+            id: u32,
+            payload: Vec<u8>,
+        }
+
+        let a = Packet {
+            id: 1,
+            payload: vec![1, 2, 3],
+        };
+        let b = Packet {
+            id: 1,
+            payload: vec![1, 9, 3, 4],
+        };
+
+        assert_eq!(b.encoded_size_hint(), 4 + 8 + 4);
+
+        // Field tag, then `id` unchanged and `payload` with two actions: a
+        // `Set` of one byte at an index and an `Append` of one byte.
+        let changeset = a.changeset(&b);
+        assert_eq!(
+            changeset.encoded_size_hint(),
+            4 + 4 + (4 + 8 + (4 + 8 + (4 + 1)) + (4 + 8 + 1))
+        );
+    }
+
+    #[test]
+    fn derive_minimal_bounds() {
+        #[derive(Debug, PartialEq, structdiff_derive::Diff)]
+        struct Inner {
+            value: u32,
+        }
+
+        #[derive(Debug, PartialEq, structdiff_derive::Diff)]
+        struct Outer {
+            inner: Inner,
+            kind: SomeEnum,
+        }
+
+        let a = Outer {
+            inner: Inner { value: 1 },
+            kind: SomeEnum::None,
+        };
+        let b = Outer {
+            inner: Inner { value: 2 },
+            kind: SomeEnum::Field2(3),
+        };
+
+        let mut target = Outer {
+            inner: Inner { value: 1 },
+            kind: SomeEnum::None,
+        };
+        a.changeset(&b).apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn cached_hash() {
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Document {
+            title: String,
+            #[diff(cached_hash)]
+            body: Hashed<Vec<String>>,
+        }
+
+        let a = Document {
+            title: "A".into(),
+            body: Hashed::with_hash(vec!["first".into()], 1),
+        };
+
+        // Matching hashes are trusted, so the differing bodies are not
+        // compared.
+        let mut b = a.clone();
+        b.body = Hashed::with_hash(vec!["second".into()], 1);
+        assert_eq!(a, b);
+        assert!(a.changeset(&b).is_none());
+
+        let mut c = a.clone();
+        c.body = Hashed::new(vec!["first".into(), "third".into()]);
+        let changeset = a.changeset(&c);
+        assert!(!changeset.is_none());
+
+        let mut target = a.clone();
+        changeset.apply(&mut target);
+        assert_eq!(target, c);
+        assert_eq!(target.body.cached_hash(), c.body.cached_hash());
+    }
+
+    #[test]
+    fn minimal_changeset() {
+        let cases: &[(&str, &str)] = &[
+            ("ABCABBA", "CBABAC"),
+            ("ABCD", "XABCD"),
+            ("ABCD", "ABD"),
+            ("", "ABC"),
+            ("ABC", ""),
+            ("ABCD", "WXYZ"),
+        ];
+
+        for (a, b) in cases {
+            let a = a.chars().map(|x| x.to_string()).collect::<Vec<_>>();
+            let b = b.chars().map(|x| x.to_string()).collect::<Vec<_>>();
+
+            let mut target = a.clone();
+            a.minimal_changeset(&b, myers::DEFAULT_MAX_COST)
+                .apply(&mut target);
+            assert_eq!(target, b);
+        }
+
+        let a = vec![1u32, 2, 3, 4];
+        let b = vec![0u32, 1, 2, 3, 4];
+
+        match a.minimal_changeset(&b, myers::DEFAULT_MAX_COST) {
+            Field::Actions(actions) => match actions.as_slice() {
+                [VecAction::Insert(0, 0)] => {}
+                x => panic!("unexpected actions {:?}", x),
+            },
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        // Over the cost cutoff, fall back to diffing by index.
+        match a.minimal_changeset(&b, 0) {
+            Field::Actions(actions) => assert_eq!(actions.len(), 5),
+            x => panic!("unexpected changeset {:?}", x),
+        }
+    }
+
+    #[test]
+    fn apply_all() {
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        #[diff(apply_ref)]
+        struct Session {
+            score: u32,
+            items: Vec<String>,
+            status: Option<String>,
+        }
+
+        let a = Session::default();
+        let b = Session {
+            score: 10,
+            items: vec!["sword".into()],
+            status: Some("online".into()),
+        };
+
+        let changeset = a.changeset(&b);
+        let mut replicas = vec![a.clone(); 3];
+        changeset.apply_all(&mut replicas);
+        assert!(replicas.iter().all(|x| x == &b));
+
+        #[cfg(feature = "rayon")]
+        {
+            let mut replicas = vec![a.clone(); 3];
+            changeset.par_apply_all(&mut replicas);
+            assert!(replicas.iter().all(|x| x == &b));
+        }
+    }
+
+    #[test]
+    fn delta_from_default() {
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Config {
+            verbose: bool,
+            threads: u32,
+            name: String,
+        }
+
+        let config = Config {
+            threads: 8,
+            ..Default::default()
+        };
+
+        let delta = config.delta_from_default();
+        match &delta {
+            Field::Changes(changes) => {
+                assert!(changes.verbose.is_none());
+                assert!(changes.name.is_none());
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+        assert_eq!(Config::from_changeset(delta), config);
+
+        // Layering an overlay on top of a base.
+        let overlay = Config {
+            name: "local".into(),
+            ..Default::default()
+        };
+        let mut layered = Config::from_changeset(config.delta_from_default());
+        overlay.delta_from_default().apply(&mut layered);
+        assert_eq!(
+            layered,
+            Config {
+                threads: 8,
+                name: "local".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn delta_from_default_collections() {
+        use std::collections::HashMap;
+
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Config {
+            paths: Vec<String>,
+            env: HashMap<String, String>,
+        }
+
+        let base = Config {
+            paths: vec!["/usr/bin".into()],
+            env: vec![
+                ("HOME".to_string(), "/root".to_string()),
+                ("LANG".to_string(), "C".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let overlay = Config {
+            paths: vec!["/opt/bin".into()],
+            env: vec![("LANG".to_string(), "en_AU".to_string())]
+                .into_iter()
+                .collect(),
+        };
+
+        // Collections in the overlay are merged into the base's.
+        let mut layered = base.clone();
+        overlay.delta_from_default().apply(&mut layered);
+        assert_eq!(layered.paths, ["/usr/bin", "/opt/bin"]);
+        assert_eq!(layered.env.len(), 2);
+        assert_eq!(layered.env["HOME"], "/root");
+        assert_eq!(layered.env["LANG"], "en_AU");
+    }
+
+    /// Records the target, level and fields of each event.
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>);
+
+    #[cfg(feature = "tracing")]
+    mod recorder {
+        use super::Recorder;
+        use crate::trace::tracing::{self, field, span, Event, Metadata};
+        use std::fmt::Debug;
+
+        struct Visitor(Vec<String>);
+
+        impl field::Visit for Visitor {
+            fn record_debug(&mut self, field: &field::Field, value: &dyn Debug) {
+                self.0.push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut visitor = Visitor(vec![]);
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push((
+                    format!("{} {}", event.metadata().target(), event.metadata().level()),
+                    visitor.0.join(" "),
+                ));
+            }
+
+            fn enter(&self, _span: &span::Id) {}
+
+            fn exit(&self, _span: &span::Id) {}
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_events() {
+        use trace::tracing::subscriber;
+
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Player {
+            score: u32,
+        }
+
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        #[diff(trace(target = "game", level = "debug"))]
+        struct Game {
+            round: u32,
+            players: Vec<Player>,
+        }
+
+        let a = Game {
+            round: 1,
+            players: vec![Player { score: 0 }, Player { score: 3 }],
+        };
+        let b = Game {
+            round: 1,
+            players: vec![Player { score: 0 }, Player { score: 5 }],
+        };
+
+        let recorder = Recorder::default();
+        let events = recorder.0.clone();
+        let mut target = a.clone();
+        subscriber::with_default(recorder, || a.changeset(&b).apply(&mut target));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].0.ends_with("TRACE"));
+        assert_eq!(events[0].1, "path=players[1].score old=3 new=5");
+        assert_eq!(events[1].0, "game DEBUG");
+        assert!(events[1].1.starts_with("path=players old="));
+    }
+
+    #[test]
+    fn canonical_maps() {
+        use crate::Canonicalize;
+        use std::collections::{HashMap, HashSet};
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(canonical)]
+        struct Inventory {
+            items: HashMap<String, u32>,
+            tags: HashSet<String>,
+        }
+
+        let a = Inventory {
+            items: (0..32).map(|i| (format!("item{:02}", i), i)).collect(),
+            tags: (0..32).map(|i| format!("tag{:02}", i)).collect(),
+        };
+        let mut b = a.clone();
+        b.items.remove("item03");
+        b.items.insert("item05".into(), 50);
+        b.items.insert("item40".into(), 40);
+        b.items.insert("item01".into(), 10);
+        b.tags.remove("tag07");
+        b.tags.insert("tag99".into());
+        b.tags.insert("tag00a".into());
+
+        let mut target = a.clone();
+        a.changeset(&b).apply(&mut target);
+        assert_eq!(target, b);
+
+        let mut changes = a.changeset(&b);
+        changes.canonicalize();
+        assert_eq!(
+            format!("{:?}", changes),
+            "Changes(InventoryChangeset { \
+             items: Changes(HashMapChangeset([\
+             Set(\"item01\", Set(10)), Remove(\"item03\"), \
+             Set(\"item05\", Set(50)), Insert(\"item40\", 40)])), \
+             tags: Changes(HashSetChangeset([\
+             Insert(\"tag00a\"), Remove(\"tag07\"), Insert(\"tag99\")])) })"
+        );
+    }
+
+    #[test]
+    fn derive_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::{HashMap, HashSet};
+        use std::hash::BuildHasherDefault;
+
+        type Hasher = BuildHasherDefault<DefaultHasher>;
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Index {
+            entries: HashMap<String, u32, Hasher>,
+            tags: HashSet<String, Hasher>,
+        }
+
+        let a = Index {
+            entries: HashMap::default(),
+            tags: HashSet::default(),
+        };
+        let mut b = a.clone();
+        b.entries.insert("a".into(), 1);
+        b.tags.insert("b".into());
+
+        let mut target = a.clone();
+        a.changeset(&b).apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn versioned_changeset() {
+        use crate::schema::{Schema, Versioned};
+
+        #[derive(
+            Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, structdiff_derive::Diff,
+        )]
+        #[diff(version = 2, migrate = "migrate_profile")]
+        struct Profile {
+            name: String,
+            age: u32,
+            email: String,
+        }
+
+        // Version 1 recorded the age in months.
+        fn migrate_profile(from: u32, changes: &mut Field<Profile, ProfileChangeset, ()>) {
+            if let (1, Field::Changes(changes)) = (from, changes) {
+                if let Field::Set(age) = &mut changes.age {
+                    *age /= 12;
+                }
+            }
+        }
+
+        let a = Profile {
+            name: "Ada".into(),
+            age: 36,
+            email: "ada@example.com".into(),
+        };
+        let b = Profile {
+            age: 37,
+            ..a.clone()
+        };
+
+        let encoded = serde_json::to_string(&Versioned::new(a.changeset(&b))).unwrap();
+        let decoded: Versioned<Field<Profile, ProfileChangeset, ()>> =
+            serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.version, Profile::VERSION);
+        let mut target = a.clone();
+        decoded.into_current().apply(&mut target);
+        assert_eq!(target, b);
+
+        // An older peer knows nothing of `email` but has since-removed `nickname`.
+        let old = r#"{
+            "version": 1,
+            "changes": {"Changes": {"name": {"Set": "Grace"}, "age": {"Set": 444}, "nickname": "None"}}
+        }"#;
+        let decoded: Versioned<Field<Profile, ProfileChangeset, ()>> =
+            serde_json::from_str(old).unwrap();
+        let mut target = a.clone();
+        decoded.into_current().apply(&mut target);
+        assert_eq!(
+            target,
+            Profile {
+                name: "Grace".into(),
+                age: 37,
+                email: "ada@example.com".into(),
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn canonical_serialize() {
+        use crate::schema::Canonical;
+        use std::collections::HashMap;
+
+        #[derive(
+            Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, structdiff_derive::Diff,
+        )]
+        #[diff(canonical, serde)]
+        struct Room {
+            name: String,
+            props: HashMap<String, u32>,
+        }
+
+        #[derive(
+            Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, structdiff_derive::Diff,
+        )]
+        #[diff(canonical, serde)]
+        struct World {
+            settings: Option<HashMap<String, u32>>,
+            rooms: Vec<Room>,
+        }
+
+        // Each map gets its own random hash state, so these iterate in
+        // different orders despite being equal.
+        let props = || (0..32).map(|i| (format!("prop{:02}", i), i)).collect();
+        let world = || World {
+            settings: Some(props()),
+            rooms: vec![Room {
+                name: "hall".into(),
+                props: props(),
+            }],
+        };
+
+        let a = World {
+            settings: None,
+            rooms: vec![],
+        };
+        let (b, c) = (world(), world());
+        assert_eq!(b, c);
+
+        let encoded = serde_json::to_string(&Canonical::new(a.changeset(&b))).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::to_string(&Canonical::new(a.changeset(&c))).unwrap()
+        );
+        assert!(encoded.contains(r#"{"prop00":0,"prop01":1,"prop02":2,"#));
+
+        let decoded: Canonical<Field<World, WorldChangeset, ()>> =
+            serde_json::from_str(&encoded).unwrap();
+        let mut target = a.clone();
+        decoded.into_inner().apply(&mut target);
+        assert_eq!(target, b);
+    }
 
-    #[derive(Debug, Default)]
-    struct FooChangeset {
-        field_a: SetField<u32, U32Changeset>,
-        field_b: SetField<String, StringChangeset>,
-        enumer: SetField<SomeEnum, SomeEnumChangeset>,
-        bar1: SetField<Bar, BarChangeset>,
-        bar: SetField<Option<Bar>, OptionChangeset<Bar>>,
-        vec: Field<Vec<String>, VecChangeset<String>, VecAction<String>>,
+    #[cfg(feature = "serde")]
+    #[test]
+    fn canonical_float_keys() {
+        use crate::schema::content::ContentSerializer;
+        use serde::{Serialize, Serializer};
+
+        struct Entries(Vec<(f64, u32)>);
+
+        impl Serialize for Entries {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+            }
+        }
+
+        let entries = vec![(f64::NAN, 0), (1.0, 1), (-0.0, 2), (0.0, 3), (-1.0, 4)];
+        let reversed = entries.iter().rev().cloned().collect();
+        let serializer = ContentSerializer {
+            human_readable: false,
+        };
+
+        assert!(
+            Entries(entries).serialize(serializer).unwrap()
+                == Entries(reversed).serialize(serializer).unwrap()
+        );
     }
 
-    impl Apply<Foo> for FooChangeset {
-        fn apply(self, target: &mut Foo) {
-            self.field_a.apply(&mut target.field_a);
-            self.field_b.apply(&mut target.field_b);
-            self.enumer.apply(&mut target.enumer);
-            self.bar1.apply(&mut target.bar1);
-            self.bar.apply(&mut target.bar);
-            self.vec.apply(&mut target.vec);
+    #[cfg(all(feature = "bumpalo", feature = "serde"))]
+    #[test]
+    fn arena_serialize() {
+        use crate::arena::DiffIn;
+
+        #[derive(
+            Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, structdiff_derive::Diff,
+        )]
+        #[diff(arena, serde)]
+        struct Log {
+            lines: Vec<String>,
+            level: Option<u8>,
+            cursor: Result<u32, String>,
         }
+
+        let arena = arena::Bump::new();
+
+        let a = Log {
+            lines: vec!["one".into(), "two".into()],
+            level: Some(1),
+            cursor: Ok(2),
+        };
+        let b = Log {
+            lines: vec!["one".into(), "2".into(), "three".into(), "four".into()],
+            level: Some(3),
+            cursor: Ok(5),
+        };
+
+        let borrowed = serde_json::to_string(&a.changeset_in(&arena, &b)).unwrap();
+        assert_eq!(borrowed, serde_json::to_string(&a.changeset(&b)).unwrap());
+
+        let decoded: Field<Log, LogChangeset, ()> = serde_json::from_str(&borrowed).unwrap();
+        let mut target = a.clone();
+        decoded.apply(&mut target);
+        assert_eq!(target, b);
     }
 
-    #[derive(Debug, Default)]
-    struct BarChangeset {
-        field_d: SetField<String, StringChangeset>,
+    #[test]
+    fn changeset_store() {
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Position {
+            x: i32,
+            y: i32,
+        }
+
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Health {
+            current: u32,
+            max: u32,
+        }
+
+        let mut store = ChangesetStore::new();
+        let position = Position { x: 1, y: 2 };
+        let health = Health {
+            current: 10,
+            max: 10,
+        };
+
+        store.insert::<Position>(7u32, position.changeset(&Position { x: 5, y: 2 }));
+        store.insert::<Position>(
+            7,
+            Position { x: 5, y: 2 }.changeset(&Position { x: 5, y: 9 }),
+        );
+        store.insert::<Health>(
+            7,
+            health.changeset(&Health {
+                current: 3,
+                max: 10,
+            }),
+        );
+        store.insert::<Health>(8, health.changeset(&health));
+        assert_eq!(store.len(), 3);
+        assert!(!store.contains::<Health>(8));
+
+        let mut target = position.clone();
+        assert!(store.apply_to(7, &mut target));
+        assert_eq!(target, Position { x: 5, y: 9 });
+        assert!(!store.apply_to(7, &mut target));
+
+        let mut target = health.clone();
+        assert!(store.apply_to(7, &mut target));
+        assert_eq!(
+            target,
+            Health {
+                current: 3,
+                max: 10
+            }
+        );
+        assert!(store.is_empty());
     }
 
-    impl Apply<Bar> for BarChangeset {
-        fn apply(self, target: &mut Bar) {
-            self.field_d.apply(&mut target.field_d);
+    #[test]
+    fn apply_validated() {
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        #[diff(apply_ref)]
+        struct Account {
+            balance: i64,
+            limit: i64,
+        }
+
+        #[derive(Debug, PartialEq)]
+        enum Rejected {
+            OverLimit,
+            Malformed(ApplyError),
         }
+
+        impl From<ApplyError> for Rejected {
+            fn from(err: ApplyError) -> Self {
+                Rejected::Malformed(err)
+            }
+        }
+
+        let within_limit = |target: &Account, changes: &Field<Account, AccountChangeset, ()>| {
+            let account = changes.preview(target)?;
+            if account.balance < -account.limit {
+                Err(Rejected::OverLimit)
+            } else {
+                Ok(())
+            }
+        };
+
+        let a = Account {
+            balance: 10,
+            limit: 100,
+        };
+
+        let mut target = a.clone();
+        let changes = a.changeset(&Account {
+            balance: -50,
+            limit: 100,
+        });
+        assert_eq!(changes.apply_validated(&mut target, &within_limit), Ok(()));
+        assert_eq!(target.balance, -50);
+
+        let changes = target.changeset(&Account {
+            balance: -50,
+            limit: 20,
+        });
+        assert_eq!(
+            changes.apply_validated(&mut target, &within_limit),
+            Err(Rejected::OverLimit)
+        );
+        assert_eq!(
+            target,
+            Account {
+                balance: -50,
+                limit: 100
+            }
+        );
     }
 
     #[test]
-    fn basic() {
-        println!("{:?}", Foo::default().changeset(&Foo::default()));
+    fn check_malformed() {
+        use smallvec::smallvec;
+        use std::collections::HashMap;
 
-        let mut g = Foo::default();
-        g.enumer = SomeEnum::Field4(22, 44);
-        g.bar = Some(Bar {
-            field_d: "Mongol".into(),
+        #[derive(Debug, Clone, Default, PartialEq, structdiff_derive::Diff)]
+        struct Document {
+            lines: Vec<String>,
+            title: Option<String>,
+            meta: HashMap<String, String>,
+        }
+
+        let target = Document {
+            lines: vec!["one".into()],
+            title: None,
+            meta: HashMap::new(),
+        };
+        let malformed = |changes: DocumentChangeset| -> Field<Document, DocumentChangeset, ()> {
+            Field::Changes(changes)
+        };
+        let accept = |_: &Document, _: &Field<Document, DocumentChangeset, ()>| Ok(());
+
+        let changes = malformed(DocumentChangeset {
+            lines: Field::Actions(smallvec![VecAction::Set(3, Field::Set("four".into()))]),
+            ..Default::default()
         });
-        g.vec = vec!["A".into(), "X".into(), "C".into()];
+        assert_eq!(
+            changes.check(&target),
+            Err(ApplyError::IndexOutOfBounds { index: 3, len: 1 })
+        );
+        assert_eq!(
+            changes.apply_validated(&mut target.clone(), &accept),
+            Err(ApplyError::IndexOutOfBounds { index: 3, len: 1 })
+        );
 
-        let mut f = Foo::default();
-        f.field_a = 123;
-        f.field_b = "ahahah".into();
-        f.enumer = SomeEnum::None;
-        f.bar = Some(Bar {
-            field_d: "Hello".into(),
+        // Indices follow the actions before them.
+        let changes = malformed(DocumentChangeset {
+            lines: Field::Actions(smallvec![
+                VecAction::Push("two".into()),
+                VecAction::Remove(0),
+                VecAction::Remove(1),
+            ]),
+            ..Default::default()
         });
-        f.vec = vec!["A".into(), "B".into(), "C".into(), "D".into(), "D".into()];
+        assert_eq!(
+            changes.check(&target),
+            Err(ApplyError::IndexOutOfBounds { index: 1, len: 1 })
+        );
 
-        // f.bar = Some(Bar { field_d: "AOWIJEWIOAJE".into() });
-        let changeset = g.changeset(&f);
-        println!("{:#?}", &changeset);
+        let changes = malformed(DocumentChangeset {
+            title: Field::Changes(OptionChangeset::SomeChangeset(Field::Set("title".into()))),
+            ..Default::default()
+        });
+        assert_eq!(
+            changes.check(&target),
+            Err(ApplyError::WrongVariant { expected: "Some" })
+        );
 
-        changeset.apply(&mut g);
-        println!("{:#?}", &g);
+        let changes = malformed(DocumentChangeset {
+            meta: Field::Changes(HashMapChangeset(smallvec![MapAction::Set(
+                "author".into(),
+                Field::Set("ada".into()),
+            )])),
+            ..Default::default()
+        });
+        assert_eq!(
+            changes.check(&target),
+            Err(ApplyError::MissingKey("\"author\"".into()))
+        );
 
-        assert_eq!(&f, &g);
+        let mut b = target.clone();
+        b.lines.insert(0, "zero".into());
+        b.title = Some("title".into());
+        b.meta.insert("author".into(), "ada".into());
+        let changes = target.changeset(&b);
+        assert_eq!(changes.check(&target), Ok(()));
+        let mut applied = target.clone();
+        assert_eq!(changes.apply_validated(&mut applied, &accept), Ok(()));
+        assert_eq!(applied, b);
+    }
+
+    #[cfg(feature = "ts")]
+    #[test]
+    fn typescript_definitions() {
+        use crate::ts::TS;
+
+        #[derive(
+            Debug,
+            Clone,
+            PartialEq,
+            serde::Serialize,
+            serde::Deserialize,
+            ts_rs::TS,
+            structdiff_derive::Diff,
+        )]
+        #[diff(ts)]
+        struct Tag {
+            label: String,
+        }
+
+        #[derive(
+            Debug,
+            Clone,
+            PartialEq,
+            serde::Serialize,
+            serde::Deserialize,
+            ts_rs::TS,
+            structdiff_derive::Diff,
+        )]
+        #[diff(ts)]
+        struct Post {
+            title: String,
+            views: u32,
+            tags: Vec<Tag>,
+        }
+
+        assert_eq!(
+            PostChangeset::decl(),
+            "type PostChangeset = { title: Field<string, null, null>, \
+             views: Field<number, null, null>, \
+             tags: Field<Array<Tag>, VecChangeset<Tag, TagChangeset, null>, \
+             VecAction<Tag, TagChangeset, null>>, };"
+        );
+        assert_eq!(
+            Field::<(), (), ()>::decl(),
+            "type Field<V, K, A> = \"None\" | { \"Set\": V } | { \"Changes\": K } | \
+             { \"Actions\": Array<A> } | \"Redacted\";"
+        );
+
+        let deps = PostChangeset::dependencies()
+            .into_iter()
+            .map(|dep| dep.ts_name)
+            .collect::<Vec<_>>();
+        for name in ["Field", "VecChangeset", "VecAction", "Tag", "TagChangeset"] {
+            assert!(deps.iter().any(|dep| dep == name), "missing {}", name);
+        }
+    }
+
+    // The conversions below match exhaustively in both directions, so a
+    // variant added to a changeset type but not to its TypeScript mirror, or
+    // the other way round, stops this from building.
+    #[cfg(feature = "ts")]
+    #[test]
+    fn typescript_mirrors() {
+        use crate::ts::{mirror, TS};
+        use smallvec::smallvec;
+
+        type Changes = Field<u32, (), ()>;
+        type Mirror = mirror::Field<u32, (), ()>;
+
+        fn field(x: Changes) -> Mirror {
+            match x {
+                Field::None => mirror::Field::None,
+                Field::Set(v) => mirror::Field::Set(v),
+                Field::Changes(k) => mirror::Field::Changes(k),
+                Field::Actions(a) => mirror::Field::Actions(a.into_vec()),
+                Field::Redacted => mirror::Field::Redacted,
+            }
+        }
+
+        fn unfield(x: Mirror) -> Changes {
+            match x {
+                mirror::Field::None => Field::None,
+                mirror::Field::Set(v) => Field::Set(v),
+                mirror::Field::Changes(k) => Field::Changes(k),
+                mirror::Field::Actions(a) => Field::Actions(a.into()),
+                mirror::Field::Redacted => Field::Redacted,
+            }
+        }
+
+        fn vec_action(x: VecAction<u32>) -> mirror::VecAction<u32, (), ()> {
+            match x {
+                VecAction::Set(i, f) => mirror::VecAction::Set(i, field(f)),
+                VecAction::Push(v) => mirror::VecAction::Push(v),
+                VecAction::Truncate(n) => mirror::VecAction::Truncate(n),
+                VecAction::Append(v) => mirror::VecAction::Append(v),
+                VecAction::Insert(i, v) => mirror::VecAction::Insert(i, v),
+                VecAction::Remove(i) => mirror::VecAction::Remove(i),
+            }
+        }
+
+        fn unvec_action(x: mirror::VecAction<u32, (), ()>) -> VecAction<u32> {
+            match x {
+                mirror::VecAction::Set(i, f) => VecAction::Set(i, unfield(f)),
+                mirror::VecAction::Push(v) => VecAction::Push(v),
+                mirror::VecAction::Truncate(n) => VecAction::Truncate(n),
+                mirror::VecAction::Append(v) => VecAction::Append(v),
+                mirror::VecAction::Insert(i, v) => VecAction::Insert(i, v),
+                mirror::VecAction::Remove(i) => VecAction::Remove(i),
+            }
+        }
+
+        fn option(x: OptionChangeset<u32>) -> mirror::OptionChangeset<u32, (), ()> {
+            match x {
+                OptionChangeset::NoneChangeset(_) => {
+                    mirror::OptionChangeset::NoneChangeset(mirror::Field::None)
+                }
+                OptionChangeset::SomeChangeset(f) => {
+                    mirror::OptionChangeset::SomeChangeset(field(f))
+                }
+            }
+        }
+
+        fn unoption(x: mirror::OptionChangeset<u32, (), ()>) -> OptionChangeset<u32> {
+            match x {
+                mirror::OptionChangeset::NoneChangeset(_) => {
+                    OptionChangeset::NoneChangeset(Field::None)
+                }
+                mirror::OptionChangeset::SomeChangeset(f) => {
+                    OptionChangeset::SomeChangeset(unfield(f))
+                }
+            }
+        }
+
+        type RealResult = ResultChangeset<u32, u32>;
+        type MirrorResult = mirror::ResultChangeset<u32, (), (), u32, (), ()>;
+
+        fn result(x: RealResult) -> MirrorResult {
+            match x {
+                ResultChangeset::OkChangeset(f) => mirror::ResultChangeset::OkChangeset(field(f)),
+                ResultChangeset::ErrChangeset(f) => mirror::ResultChangeset::ErrChangeset(field(f)),
+            }
+        }
+
+        fn unresult(x: MirrorResult) -> RealResult {
+            match x {
+                mirror::ResultChangeset::OkChangeset(f) => ResultChangeset::OkChangeset(unfield(f)),
+                mirror::ResultChangeset::ErrChangeset(f) => {
+                    ResultChangeset::ErrChangeset(unfield(f))
+                }
+            }
+        }
+
+        fn map_action(x: MapAction<u32, u32>) -> mirror::MapAction<u32, u32, (), ()> {
+            match x {
+                MapAction::Insert(k, v) => mirror::MapAction::Insert(k, v),
+                MapAction::Remove(k) => mirror::MapAction::Remove(k),
+                MapAction::Set(k, f) => mirror::MapAction::Set(k, field(f)),
+            }
+        }
+
+        fn unmap_action(x: mirror::MapAction<u32, u32, (), ()>) -> MapAction<u32, u32> {
+            match x {
+                mirror::MapAction::Insert(k, v) => MapAction::Insert(k, v),
+                mirror::MapAction::Remove(k) => MapAction::Remove(k),
+                mirror::MapAction::Set(k, f) => MapAction::Set(k, unfield(f)),
+            }
+        }
+
+        fn set_action(x: SetAction<u32>) -> mirror::SetAction<u32> {
+            match x {
+                SetAction::Insert(v) => mirror::SetAction::Insert(v),
+                SetAction::Remove(v) => mirror::SetAction::Remove(v),
+            }
+        }
+
+        fn unset_action(x: mirror::SetAction<u32>) -> SetAction<u32> {
+            match x {
+                mirror::SetAction::Insert(v) => SetAction::Insert(v),
+                mirror::SetAction::Remove(v) => SetAction::Remove(v),
+            }
+        }
+
+        fn hashed(x: HashedChangeset<u32>) -> mirror::HashedChangeset<u32, (), ()> {
+            let HashedChangeset { hash, changes } = x;
+            mirror::HashedChangeset {
+                hash,
+                changes: field(changes),
+            }
+        }
+
+        let roundtrip = |x: VecAction<u32>| format!("{:?}", unvec_action(vec_action(x)));
+        let set = VecAction::Set(1, Field::Actions(smallvec![(), ()]));
+        assert_eq!(roundtrip(set), "Set(1, Actions([(), ()]))");
+        assert_eq!(roundtrip(VecAction::Insert(0, 7)), "Insert(0, 7)");
+        assert_eq!(format!("{:?}", unfield(field(Field::Redacted))), "Redacted");
+        assert_eq!(
+            format!(
+                "{:?}",
+                unoption(option(OptionChangeset::SomeChangeset(Field::Set(2))))
+            ),
+            "SomeChangeset(Set(2))"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                unresult(result(ResultChangeset::ErrChangeset(Field::None)))
+            ),
+            "ErrChangeset(None)"
+        );
+        assert_eq!(
+            format!("{:?}", unmap_action(map_action(MapAction::Remove(3)))),
+            "Remove(3)"
+        );
+        assert_eq!(
+            format!("{:?}", unset_action(set_action(SetAction::Insert(4)))),
+            "Insert(4)"
+        );
+
+        // Hashes are strings in JSON so that they survive JavaScript numbers.
+        let changes = HashedChangeset {
+            hash: u64::MAX,
+            changes: Field::Set(5u32),
+        };
+        let encoded = serde_json::to_string(&changes).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"hash":"18446744073709551615","changes":{"Set":5}}"#
+        );
+        let decoded: HashedChangeset<u32> = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.hash, u64::MAX);
+        assert!(mirror::HashedChangeset::<u32, (), ()>::decl().contains("hash: string"));
+        assert_eq!(hashed(changes).hash, u64::MAX);
+    }
+
+    #[test]
+    fn redact_sensitive() {
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Credentials {
+            user: String,
+            #[diff(sensitive)]
+            password: String,
+            #[diff(sensitive)]
+            token: Option<String>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(redact)]
+        struct Service {
+            name: String,
+            credentials: Credentials,
+        }
+
+        let a = Service {
+            name: "db".into(),
+            credentials: Credentials {
+                user: "admin".into(),
+                password: "hunter2".into(),
+                token: None,
+            },
+        };
+        let b = Service {
+            name: "db".into(),
+            credentials: Credentials {
+                user: "root".into(),
+                password: "correct horse".into(),
+                token: None,
+            },
+        };
+
+        let changes = a.changeset(&b);
+        assert_eq!(
+            format!("{:?}", changes),
+            "Changes(ServiceChangeset { name: None, credentials: Changes(CredentialsChangeset { \
+             user: Set(\"root\"), password: Redacted, token: None }) })"
+        );
+
+        let mut redacted = a.changeset(&b);
+        redacted.redact();
+        match &redacted {
+            Field::Changes(ServiceChangeset {
+                credentials: Field::Changes(credentials),
+                ..
+            }) => {
+                assert!(matches!(credentials.password, Field::Redacted));
+                assert!(credentials.token.is_none());
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        let mut target = a.clone();
+        changes.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn redact_whole_values() {
+        use std::collections::HashMap;
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Credentials {
+            user: String,
+            #[diff(sensitive)]
+            password: String,
+        }
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(redact)]
+        struct Vault {
+            name: String,
+            primary: Option<Credentials>,
+            history: Vec<Credentials>,
+            by_host: HashMap<String, Credentials>,
+        }
+
+        let credentials = |password: &str| Credentials {
+            user: "admin".into(),
+            password: password.into(),
+        };
+        let a = Vault {
+            name: "db".into(),
+            primary: None,
+            history: vec![],
+            by_host: HashMap::new(),
+        };
+        let b = Vault {
+            name: "cache".into(),
+            primary: Some(credentials("hunter2")),
+            history: vec![credentials("battery")],
+            by_host: vec![("db".to_string(), credentials("staple"))]
+                .into_iter()
+                .collect(),
+        };
+
+        let changes = a.changeset(&b);
+        let debug = format!("{:?}", changes);
+        for password in ["hunter2", "battery", "staple"] {
+            assert!(
+                !debug.contains(password),
+                "{} leaked in {}",
+                password,
+                debug
+            );
+        }
+        assert!(debug.contains("primary: Set(Redacted)"), "{}", debug);
+        assert!(debug.contains("Append(Redacted)"), "{}", debug);
+        assert!(debug.contains("Insert(\"db\", Redacted)"), "{}", debug);
+
+        let mut redacted = a.changeset(&b);
+        redacted.redact();
+        match &redacted {
+            Field::Changes(changes) => {
+                assert!(matches!(changes.name, Field::Set(_)));
+                assert!(matches!(changes.primary, Field::Redacted));
+                assert!(matches!(changes.history, Field::Redacted));
+                assert!(matches!(changes.by_host, Field::Redacted));
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        let mut target = a.clone();
+        assert_eq!(
+            redacted.apply_validated(&mut target, &|_: &Vault, _: &_| Ok::<_, ApplyError>(())),
+            Err(ApplyError::Redacted)
+        );
+        assert_eq!(target, a);
+
+        changes.apply(&mut target);
+        assert_eq!(target, b);
+    }
+
+    #[test]
+    fn diff_across() {
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct ConfigV1 {
+            host: String,
+            port: u16,
+            timeout_ms: u64,
+        }
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(across(ConfigV1))]
+        struct ConfigV2 {
+            host: String,
+            #[diff(across(rename = "port"))]
+            listen_port: u16,
+            #[diff(across(rename = "timeout_ms", with = "ms_to_secs"))]
+            timeout_secs: u64,
+            #[diff(across(skip))]
+            tls: bool,
+        }
+
+        fn ms_to_secs(ms: &u64) -> u64 {
+            ms / 1000
+        }
+
+        let v1 = ConfigV1 {
+            host: "example.com".into(),
+            port: 8080,
+            timeout_ms: 30_000,
+        };
+        let mut v2 = ConfigV2 {
+            host: "example.com".into(),
+            listen_port: 80,
+            timeout_secs: 30,
+            tls: true,
+        };
+
+        let changes = v2.changeset_across(&v1);
+        match &changes {
+            Field::Changes(changes) => {
+                assert!(changes.host.is_none());
+                assert!(changes.timeout_secs.is_none());
+                assert!(changes.tls.is_none());
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        changes.apply(&mut v2);
+        assert_eq!(v2.listen_port, 8080);
+        assert!(v2.tls);
+        assert!(v2.changeset_across(&v1).is_none());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_sensitive() {
+        use trace::tracing::subscriber;
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Credentials {
+            user: String,
+            #[diff(sensitive)]
+            password: String,
+        }
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        struct Service {
+            credentials: Credentials,
+            fallback: Option<Credentials>,
+        }
+
+        let credentials = |password: &str| Credentials {
+            user: "admin".into(),
+            password: password.into(),
+        };
+        let a = Service {
+            credentials: credentials("hunter2"),
+            fallback: None,
+        };
+        let b = Service {
+            credentials: credentials("correcthorse"),
+            fallback: Some(credentials("battery")),
+        };
+
+        let recorder = Recorder::default();
+        let events = recorder.0.clone();
+        let changes = a.changeset(&b);
+        let debug = format!("{:?}", changes);
+        for password in ["hunter2", "correcthorse", "battery"] {
+            assert!(
+                !debug.contains(password),
+                "{} leaked in {}",
+                password,
+                debug
+            );
+        }
+
+        let mut target = a.clone();
+        subscriber::with_default(recorder, || changes.apply(&mut target));
+        assert_eq!(target, b);
+
+        let events = events.lock().unwrap();
+        let fields = events
+            .iter()
+            .map(|(_, fields)| &**fields)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                "path=credentials.password redacted=true",
+                "path=credentials redacted=true",
+                "path=fallback redacted=true",
+            ]
+        );
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn arena_redacted_debug() {
+        use crate::arena::DiffIn;
+
+        #[derive(Debug, Clone, PartialEq, structdiff_derive::Diff)]
+        #[diff(arena)]
+        struct Login {
+            user: String,
+            #[diff(sensitive)]
+            password: String,
+        }
+
+        let arena = arena::Bump::new();
+        let a = Login {
+            user: "admin".into(),
+            password: "hunter2".into(),
+        };
+        let b = Login {
+            user: "root".into(),
+            password: "correcthorse".into(),
+        };
+
+        assert_eq!(
+            format!("{:?}", a.changeset_in(&arena, &b)),
+            "Changes(LoginChangesetIn { user: Set(\"root\"), password: Redacted })"
+        );
+    }
+
+    #[cfg(feature = "bevy_reflect")]
+    #[test]
+    fn reflect_diff() {
+        use crate::reflect::ReflectChangeset;
+        use bevy_reflect::Reflect;
+        use std::collections::HashMap;
+
+        #[derive(Debug, Clone, PartialEq, Reflect)]
+        struct Transform {
+            translation: (f32, f32),
+            scale: f32,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Reflect)]
+        struct Sprite {
+            name: String,
+            transform: Transform,
+            layers: Vec<u32>,
+            stats: HashMap<String, u32>,
+        }
+
+        let a = Sprite {
+            name: "player".into(),
+            transform: Transform {
+                translation: (0.0, 1.0),
+                scale: 1.0,
+            },
+            layers: vec![1, 2, 3],
+            stats: vec![("hp".to_string(), 10), ("mp".to_string(), 5)]
+                .into_iter()
+                .collect(),
+        };
+        let mut b = a.clone();
+        b.transform.translation.1 = 2.0;
+        b.layers = vec![1, 4];
+        b.stats.remove("mp");
+        b.stats.insert("hp".into(), 7);
+        b.stats.insert("xp".into(), 100);
+
+        assert!(diff_reflect(&a, &a).unwrap().is_none());
+
+        let changes = diff_reflect(&a, &b).unwrap().unwrap();
+        match &changes {
+            ReflectChangeset::Struct(fields) => {
+                let names = fields.iter().map(|(name, _)| &**name).collect::<Vec<_>>();
+                assert_eq!(names, ["transform", "layers", "stats"]);
+            }
+            x => panic!("unexpected changeset {:?}", x),
+        }
+
+        let mut target = a.clone();
+        changes.apply(&mut target).unwrap();
+        assert_eq!(target, b);
+
+        let mut other = vec![1u32];
+        assert!(changes.apply(&mut other).is_err());
+    }
+
+    #[cfg(feature = "bevy_reflect")]
+    #[test]
+    fn reflect_apply_mismatched_types() {
+        use crate::reflect::{ListAction, MapAction, ReflectApplyError, ReflectChangeset};
+        use bevy_reflect::ReflectKind;
+        use std::collections::HashMap;
+
+        // Concrete collections panic on values they cannot convert.
+        let push = ReflectChangeset::List(vec![ListAction::Push(Box::new("one".to_string()))]);
+        let mut list = vec![1u32];
+        assert!(matches!(
+            push.apply(&mut list),
+            Err(ReflectApplyError::Apply(_))
+        ));
+        assert_eq!(list, [1]);
+
+        let insert = ReflectChangeset::Map(vec![MapAction::Insert(
+            Box::new("a".to_string()),
+            Box::new(1.5f32),
+        )]);
+        let mut map = HashMap::<String, u32>::new();
+        assert!(matches!(
+            insert.apply(&mut map),
+            Err(ReflectApplyError::Apply(_))
+        ));
+        assert!(map.is_empty());
+
+        let changes = diff_reflect(&[1u32, 2], &[1u32, 3]).unwrap().unwrap();
+        let mut tuple = (1u32, 2u32);
+        match changes.apply(&mut tuple) {
+            Err(ReflectApplyError::MismatchedKinds { expected, found }) => {
+                assert_eq!(expected, ReflectKind::Array);
+                assert_eq!(found, ReflectKind::Tuple);
+            }
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[cfg(feature = "bevy_reflect")]
+    #[test]
+    fn reflect_diff_opaque_without_clone() {
+        use crate::reflect::{ListAction, ReflectApplyError, ReflectChangeset};
+        use bevy_reflect::{
+            ApplyError, PartialReflect, Reflect, ReflectMut, ReflectOwned, ReflectRef, TypeInfo,
+            TypePath,
+        };
+
+        // Opaque, and left on the default `reflect_clone`, which fails.
+        #[derive(Debug, TypePath)]
+        struct Handle(u32);
+
+        impl PartialReflect for Handle {
+            fn get_represented_type_info(&self) -> Option<&'static TypeInfo> {
+                None
+            }
+
+            fn into_partial_reflect(self: Box<Self>) -> Box<dyn PartialReflect> {
+                self
+            }
+
+            fn as_partial_reflect(&self) -> &dyn PartialReflect {
+                self
+            }
+
+            fn as_partial_reflect_mut(&mut self) -> &mut dyn PartialReflect {
+                self
+            }
+
+            fn try_into_reflect(
+                self: Box<Self>,
+            ) -> Result<Box<dyn Reflect>, Box<dyn PartialReflect>> {
+                Err(self)
+            }
+
+            fn try_as_reflect(&self) -> Option<&dyn Reflect> {
+                None
+            }
+
+            fn try_as_reflect_mut(&mut self) -> Option<&mut dyn Reflect> {
+                None
+            }
+
+            fn try_apply(&mut self, _value: &dyn PartialReflect) -> Result<(), ApplyError> {
+                Ok(())
+            }
+
+            fn reflect_ref(&self) -> ReflectRef<'_> {
+                ReflectRef::Opaque(self)
+            }
+
+            fn reflect_mut(&mut self) -> ReflectMut<'_> {
+                ReflectMut::Opaque(self)
+            }
+
+            fn reflect_owned(self: Box<Self>) -> ReflectOwned {
+                ReflectOwned::Opaque(self)
+            }
+        }
+
+        assert!(diff_reflect(&Handle(1), &Handle(2)).is_err());
+
+        // Dynamic lists take values of any type, so only the clone fails.
+        let changes = ReflectChangeset::List(vec![ListAction::Push(Box::new(Handle(1)))]);
+        let mut target = bevy_reflect::DynamicList::default();
+        assert!(matches!(
+            changes.apply(&mut target),
+            Err(ReflectApplyError::Clone(_))
+        ));
     }
 }
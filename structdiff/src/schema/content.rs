@@ -0,0 +1,573 @@
+use serde::ser::{self, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Ordered so that any two keys compare, whatever they hold: values of
+/// different kinds by the order of the variants below, and floats by
+/// `total_cmp`, NaN included.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum Content {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(Float<f32>),
+    F64(Float<f64>),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Content>),
+    Unit,
+    UnitStruct(&'static str),
+    UnitVariant(&'static str, u32, &'static str),
+    NewtypeStruct(&'static str, Box<Content>),
+    NewtypeVariant(&'static str, u32, &'static str, Box<Content>),
+    Seq(Vec<Content>),
+    Tuple(Vec<Content>),
+    TupleStruct(&'static str, Vec<Content>),
+    TupleVariant(&'static str, u32, &'static str, Vec<Content>),
+    Map(Vec<(Content, Content)>),
+    /// Fields skipped by the value's serializer are held as `None`.
+    Struct(&'static str, Vec<(&'static str, Option<Content>)>),
+    StructVariant(
+        &'static str,
+        u32,
+        &'static str,
+        Vec<(&'static str, Option<Content>)>,
+    ),
+}
+
+/// A float compared with `total_cmp`.
+#[derive(Clone, Copy)]
+pub struct Float<T>(T);
+
+macro_rules! impl_total_order {
+    ($ty:ty) => {
+        impl PartialEq for Float<$ty> {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for Float<$ty> {}
+
+        impl PartialOrd for Float<$ty> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Float<$ty> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+    };
+}
+
+impl_total_order!(f32);
+impl_total_order!(f64);
+
+impl Serialize for Content {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::{
+            SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+            SerializeTupleStruct, SerializeTupleVariant,
+        };
+
+        match self {
+            Content::Bool(v) => serializer.serialize_bool(*v),
+            Content::I8(v) => serializer.serialize_i8(*v),
+            Content::I16(v) => serializer.serialize_i16(*v),
+            Content::I32(v) => serializer.serialize_i32(*v),
+            Content::I64(v) => serializer.serialize_i64(*v),
+            Content::I128(v) => serializer.serialize_i128(*v),
+            Content::U8(v) => serializer.serialize_u8(*v),
+            Content::U16(v) => serializer.serialize_u16(*v),
+            Content::U32(v) => serializer.serialize_u32(*v),
+            Content::U64(v) => serializer.serialize_u64(*v),
+            Content::U128(v) => serializer.serialize_u128(*v),
+            Content::F32(v) => serializer.serialize_f32(v.0),
+            Content::F64(v) => serializer.serialize_f64(v.0),
+            Content::Char(v) => serializer.serialize_char(*v),
+            Content::String(v) => serializer.serialize_str(v),
+            Content::Bytes(v) => serializer.serialize_bytes(v),
+            Content::None => serializer.serialize_none(),
+            Content::Some(v) => serializer.serialize_some(v),
+            Content::Unit => serializer.serialize_unit(),
+            Content::UnitStruct(name) => serializer.serialize_unit_struct(name),
+            Content::UnitVariant(name, index, variant) => {
+                serializer.serialize_unit_variant(name, *index, variant)
+            }
+            Content::NewtypeStruct(name, v) => serializer.serialize_newtype_struct(name, v),
+            Content::NewtypeVariant(name, index, variant, v) => {
+                serializer.serialize_newtype_variant(name, *index, variant, v)
+            }
+            Content::Seq(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Content::Tuple(items) => {
+                let mut tuple = serializer.serialize_tuple(items.len())?;
+                for item in items {
+                    tuple.serialize_element(item)?;
+                }
+                tuple.end()
+            }
+            Content::TupleStruct(name, items) => {
+                let mut tuple = serializer.serialize_tuple_struct(name, items.len())?;
+                for item in items {
+                    tuple.serialize_field(item)?;
+                }
+                tuple.end()
+            }
+            Content::TupleVariant(name, index, variant, items) => {
+                let mut tuple =
+                    serializer.serialize_tuple_variant(name, *index, variant, items.len())?;
+                for item in items {
+                    tuple.serialize_field(item)?;
+                }
+                tuple.end()
+            }
+            Content::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Content::Struct(name, fields) => {
+                let len = fields.iter().filter(|(_, v)| v.is_some()).count();
+                let mut s = serializer.serialize_struct(name, len)?;
+                for (key, value) in fields {
+                    match value {
+                        Some(value) => s.serialize_field(key, value)?,
+                        None => s.skip_field(key)?,
+                    }
+                }
+                s.end()
+            }
+            Content::StructVariant(name, index, variant, fields) => {
+                let len = fields.iter().filter(|(_, v)| v.is_some()).count();
+                let mut s = serializer.serialize_struct_variant(name, *index, variant, len)?;
+                for (key, value) in fields {
+                    match value {
+                        Some(value) => s.serialize_field(key, value)?,
+                        None => s.skip_field(key)?,
+                    }
+                }
+                s.end()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ContentSerializer {
+    pub human_readable: bool,
+}
+
+impl ContentSerializer {
+    fn content<T: Serialize + ?Sized>(self, value: &T) -> Result<Content, Error> {
+        value.serialize(self)
+    }
+}
+
+impl Serializer for ContentSerializer {
+    type Ok = Content;
+    type Error = Error;
+    type SerializeSeq = SeqContent;
+    type SerializeTuple = SeqContent;
+    type SerializeTupleStruct = SeqContent;
+    type SerializeTupleVariant = SeqContent;
+    type SerializeMap = MapContent;
+    type SerializeStruct = StructContent;
+    type SerializeStructVariant = StructContent;
+
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Content, Error> {
+        Ok(Content::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Content, Error> {
+        Ok(Content::I8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Content, Error> {
+        Ok(Content::I16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Content, Error> {
+        Ok(Content::I32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Content, Error> {
+        Ok(Content::I64(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Content, Error> {
+        Ok(Content::I128(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Content, Error> {
+        Ok(Content::U8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Content, Error> {
+        Ok(Content::U16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Content, Error> {
+        Ok(Content::U32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Content, Error> {
+        Ok(Content::U64(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Content, Error> {
+        Ok(Content::U128(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Content, Error> {
+        Ok(Content::F32(Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Content, Error> {
+        Ok(Content::F64(Float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Content, Error> {
+        Ok(Content::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Content, Error> {
+        Ok(Content::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Content, Error> {
+        Ok(Content::Bytes(v.to_owned()))
+    }
+
+    fn serialize_none(self) -> Result<Content, Error> {
+        Ok(Content::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Content, Error> {
+        Ok(Content::Some(Box::new(self.content(value)?)))
+    }
+
+    fn serialize_unit(self) -> Result<Content, Error> {
+        Ok(Content::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Content, Error> {
+        Ok(Content::UnitStruct(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<Content, Error> {
+        Ok(Content::UnitVariant(name, index, variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Content, Error> {
+        Ok(Content::NewtypeStruct(name, Box::new(self.content(value)?)))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Content, Error> {
+        let value = Box::new(self.content(value)?);
+        Ok(Content::NewtypeVariant(name, index, variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqContent, Error> {
+        Ok(SeqContent::new(self, SeqKind::Seq, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqContent, Error> {
+        Ok(SeqContent::new(self, SeqKind::Tuple, len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<SeqContent, Error> {
+        Ok(SeqContent::new(self, SeqKind::TupleStruct(name), len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqContent, Error> {
+        let kind = SeqKind::TupleVariant(name, index, variant);
+        Ok(SeqContent::new(self, kind, len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapContent, Error> {
+        Ok(MapContent {
+            serializer: self,
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructContent, Error> {
+        Ok(StructContent::new(self, StructKind::Struct(name), len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructContent, Error> {
+        let kind = StructKind::StructVariant(name, index, variant);
+        Ok(StructContent::new(self, kind, len))
+    }
+}
+
+pub enum SeqKind {
+    Seq,
+    Tuple,
+    TupleStruct(&'static str),
+    TupleVariant(&'static str, u32, &'static str),
+}
+
+pub struct SeqContent {
+    serializer: ContentSerializer,
+    kind: SeqKind,
+    items: Vec<Content>,
+}
+
+impl SeqContent {
+    fn new(serializer: ContentSerializer, kind: SeqKind, len: usize) -> Self {
+        SeqContent {
+            serializer,
+            kind,
+            items: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(self.serializer.content(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Content, Error> {
+        Ok(match self.kind {
+            SeqKind::Seq => Content::Seq(self.items),
+            SeqKind::Tuple => Content::Tuple(self.items),
+            SeqKind::TupleStruct(name) => Content::TupleStruct(name, self.items),
+            SeqKind::TupleVariant(name, index, variant) => {
+                Content::TupleVariant(name, index, variant, self.items)
+            }
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}
+
+pub struct MapContent {
+    serializer: ContentSerializer,
+    entries: Vec<(Content, Content)>,
+    key: Option<Content>,
+}
+
+impl ser::SerializeMap for MapContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(self.serializer.content(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return Err(ser::Error::custom("map value without a key")),
+        };
+        self.entries.push((key, self.serializer.content(value)?));
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Content, Error> {
+        self.entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Content::Map(self.entries))
+    }
+}
+
+pub enum StructKind {
+    Struct(&'static str),
+    StructVariant(&'static str, u32, &'static str),
+}
+
+pub struct StructContent {
+    serializer: ContentSerializer,
+    kind: StructKind,
+    fields: Vec<(&'static str, Option<Content>)>,
+}
+
+impl StructContent {
+    fn new(serializer: ContentSerializer, kind: StructKind, len: usize) -> Self {
+        StructContent {
+            serializer,
+            kind,
+            fields: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.fields
+            .push((key, Some(self.serializer.content(value)?)));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Content, Error> {
+        Ok(match self.kind {
+            StructKind::Struct(name) => Content::Struct(name, self.fields),
+            StructKind::StructVariant(name, index, variant) => {
+                Content::StructVariant(name, index, variant, self.fields)
+            }
+        })
+    }
+}
+
+impl ser::SerializeStruct for StructContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+        self.fields.push((key, None));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructContent {
+    type Ok = Content;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+        self.fields.push((key, None));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Content, Error> {
+        self.finish()
+    }
+}